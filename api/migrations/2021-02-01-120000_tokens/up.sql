@@ -0,0 +1,11 @@
+CREATE TABLE tokens (
+  id SERIAL PRIMARY KEY,
+  owner INT NOT NULL REFERENCES users ON DELETE CASCADE,
+  name VARCHAR NOT NULL,
+  hash VARCHAR NOT NULL,
+  read_only BOOL NOT NULL DEFAULT FALSE,
+  tz VARCHAR NOT NULL,
+  expires_at TIMESTAMP WITH TIME ZONE,
+  last_used_at TIMESTAMP WITH TIME ZONE,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
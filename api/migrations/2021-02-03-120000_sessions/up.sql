@@ -0,0 +1,10 @@
+CREATE TABLE sessions (
+  id UUID PRIMARY KEY,
+  owner INT NOT NULL REFERENCES users ON DELETE CASCADE,
+  tz VARCHAR NOT NULL,
+  user_agent VARCHAR,
+  ip VARCHAR,
+  expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+  last_seen_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
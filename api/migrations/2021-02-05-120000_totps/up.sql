@@ -0,0 +1,11 @@
+CREATE TABLE totps (
+  owner INT PRIMARY KEY REFERENCES users ON DELETE CASCADE,
+  secret VARCHAR NOT NULL,
+  is_enabled BOOL NOT NULL DEFAULT FALSE,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+CREATE TABLE recovery_codes (
+  id SERIAL PRIMARY KEY,
+  owner INT NOT NULL REFERENCES users ON DELETE CASCADE,
+  hash VARCHAR NOT NULL
+);
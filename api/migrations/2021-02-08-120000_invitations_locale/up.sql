@@ -0,0 +1 @@
+ALTER TABLE invitations ADD COLUMN locale VARCHAR NOT NULL DEFAULT 'en';
@@ -0,0 +1,10 @@
+CREATE TABLE reminders (
+  owner INT PRIMARY KEY REFERENCES users ON DELETE CASCADE,
+  is_enabled BOOL NOT NULL DEFAULT FALSE,
+  by_email BOOL NOT NULL DEFAULT TRUE,
+  webhook_url VARCHAR,
+  horizon_hours INT NOT NULL DEFAULT 24,
+  tz VARCHAR NOT NULL,
+  locale VARCHAR NOT NULL DEFAULT 'en',
+  last_sent_at TIMESTAMP WITH TIME ZONE
+);
@@ -0,0 +1,22 @@
+CREATE TABLE webhooks (
+  id SERIAL PRIMARY KEY,
+  owner INT NOT NULL REFERENCES users ON DELETE CASCADE,
+  url VARCHAR NOT NULL,
+  secret VARCHAR NOT NULL,
+  events VARCHAR[] NOT NULL DEFAULT '{}', -- empty for all
+  is_active BOOL NOT NULL DEFAULT TRUE,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+CREATE TABLE webhook_deliveries (
+  id SERIAL PRIMARY KEY,
+  webhook INT NOT NULL REFERENCES webhooks ON DELETE CASCADE,
+  event VARCHAR NOT NULL,
+  payload TEXT NOT NULL,
+  attempts INT NOT NULL DEFAULT 0,
+  status INT,
+  error VARCHAR,
+  next_attempt_at TIMESTAMP WITH TIME ZONE,
+  delivered_at TIMESTAMP WITH TIME ZONE,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+CREATE INDEX webhook_deliveries_next_attempt_at_idx ON webhook_deliveries (next_attempt_at);
@@ -0,0 +1,8 @@
+CREATE TABLE comments (
+  id SERIAL PRIMARY KEY,
+  task INT NOT NULL REFERENCES tasks ON DELETE CASCADE,
+  author INT NOT NULL REFERENCES users ON DELETE CASCADE,
+  body TEXT NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+CREATE INDEX comments_task_idx ON comments (task);
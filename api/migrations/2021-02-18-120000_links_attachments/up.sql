@@ -0,0 +1,19 @@
+CREATE TABLE links (
+  id SERIAL PRIMARY KEY,
+  task INT NOT NULL REFERENCES tasks ON DELETE CASCADE,
+  url VARCHAR NOT NULL,
+  label VARCHAR,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  UNIQUE (task, url)
+);
+INSERT INTO links (task, url) SELECT id, link FROM tasks WHERE link IS NOT NULL;
+CREATE TABLE attachments (
+  id SERIAL PRIMARY KEY,
+  task INT NOT NULL REFERENCES tasks ON DELETE CASCADE,
+  uploader INT NOT NULL REFERENCES users ON DELETE CASCADE,
+  name VARCHAR NOT NULL,
+  mime VARCHAR NOT NULL,
+  size INT NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+CREATE INDEX attachments_task_idx ON attachments (task);
@@ -0,0 +1,3 @@
+ALTER TABLE tasks DROP COLUMN project;
+DROP TABLE project_members;
+DROP TABLE projects;
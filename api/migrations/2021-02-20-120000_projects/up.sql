@@ -0,0 +1,14 @@
+CREATE TABLE projects (
+  id SERIAL PRIMARY KEY,
+  name VARCHAR NOT NULL UNIQUE,
+  owner INT NOT NULL REFERENCES users ON DELETE CASCADE,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+CREATE TABLE project_members (
+  project INT REFERENCES projects ON DELETE CASCADE,
+  member INT REFERENCES users ON DELETE CASCADE,
+  edit BOOLEAN NOT NULL DEFAULT TRUE,
+  PRIMARY KEY (project, member)
+);
+ALTER TABLE tasks ADD COLUMN project INT REFERENCES projects ON DELETE SET NULL;
+CREATE INDEX tasks_project_idx ON tasks (project);
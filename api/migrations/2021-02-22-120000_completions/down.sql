@@ -0,0 +1,3 @@
+DROP TABLE completions;
+ALTER TABLE tasks DROP COLUMN archived_by;
+ALTER TABLE tasks DROP COLUMN archived_at;
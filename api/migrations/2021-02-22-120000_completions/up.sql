@@ -0,0 +1,14 @@
+ALTER TABLE tasks ADD COLUMN archived_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE tasks ADD COLUMN archived_by INT REFERENCES users ON DELETE SET NULL;
+UPDATE tasks SET archived_at = updated_at, archived_by = assign WHERE is_archived;
+CREATE TABLE completions (
+  id SERIAL PRIMARY KEY,
+  task INT NOT NULL REFERENCES tasks ON DELETE CASCADE,
+  archived_by INT REFERENCES users ON DELETE SET NULL,
+  archived_at TIMESTAMP WITH TIME ZONE NOT NULL,
+  reverted_by INT REFERENCES users ON DELETE SET NULL,
+  reverted_at TIMESTAMP WITH TIME ZONE
+);
+CREATE INDEX completions_task_idx ON completions (task);
+INSERT INTO completions (task, archived_by, archived_at)
+SELECT id, archived_by, archived_at FROM tasks WHERE is_archived;
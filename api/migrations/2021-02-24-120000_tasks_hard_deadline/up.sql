@@ -0,0 +1 @@
+ALTER TABLE tasks ADD COLUMN is_hard_deadline BOOLEAN NOT NULL DEFAULT FALSE;
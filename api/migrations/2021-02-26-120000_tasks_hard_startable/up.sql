@@ -0,0 +1,3 @@
+ALTER TABLE tasks ADD COLUMN is_hard_startable BOOLEAN NOT NULL DEFAULT FALSE;
+-- startables so far have been hard ones
+UPDATE tasks SET is_hard_startable = TRUE WHERE startable IS NOT NULL;
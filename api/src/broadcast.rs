@@ -0,0 +1,151 @@
+use actix_web::web::Bytes;
+use diesel::prelude::*;
+use futures::channel::mpsc;
+use serde::Serialize;
+use std::sync::Mutex;
+
+use crate::errors;
+use crate::models;
+use crate::webhook;
+
+// server-sent events to open home views, of the clients connected to this process
+#[derive(Default)]
+pub struct Broadcaster {
+    clients: Mutex<Vec<Client>>,
+}
+
+struct Client {
+    user: i32,
+    sender: mpsc::UnboundedSender<Bytes>,
+}
+
+// what changed, for clients to refetch rather than to patch
+pub struct Message {
+    event: webhook::Event,
+    recipients: Vec<i32>,
+    data: Data,
+}
+
+#[derive(Serialize)]
+struct Data {
+    tasks: Vec<i32>,
+    arrows: Vec<models::Arrow>,
+}
+
+impl Broadcaster {
+    pub fn subscribe(&self, user: i32) -> mpsc::UnboundedReceiver<Bytes> {
+        let (sender, receiver) = mpsc::unbounded();
+        // let EventSource retry in a few seconds when disconnected
+        let _ = sender.unbounded_send(Bytes::from_static(b"retry: 3000\n\n"));
+        self.clients.lock().unwrap().push(Client {
+            user: user,
+            sender: sender,
+        });
+        receiver
+    }
+    pub fn send(&self, message: &Message) {
+        // not to wake clients up for nothing
+        if message.data.tasks.is_empty() && message.data.arrows.is_empty() {
+            return
+        }
+        let chunk = Bytes::from(message.to_string());
+        self.clients.lock().unwrap().retain(|c| {
+            !message.recipients.contains(&c.user) || c.sender.unbounded_send(chunk.clone()).is_ok()
+        });
+    }
+    // keeps proxies from closing idle streams, and forgets closed ones
+    pub fn ping(&self) {
+        self.clients.lock().unwrap().retain(|c| {
+            c.sender.unbounded_send(Bytes::from_static(b": ping\n\n")).is_ok()
+        });
+    }
+}
+
+impl Message {
+    // to everyone with permission on the assignees, or in the projects
+    pub fn new(
+        event: webhook::Event,
+        ids: &Vec<i32>,
+        arrows: &Vec<models::Arrow>,
+        conn: &models::Conn,
+    ) -> Result<Self, errors::ServiceError> {
+        use crate::schema::permissions::dsl::{permissions, subject, object};
+        use crate::schema::project_members::dsl::{project_members, member};
+        use crate::schema::tasks::dsl::{tasks, id, assign, project};
+
+        let mut involved = ids.clone();
+        involved.extend(arrows.iter().flat_map(|arw| vec![arw.source, arw.target]));
+        let assigns = tasks.filter(id.eq_any(&involved)).select(assign).distinct().load::<i32>(conn)?;
+        let mut recipients = permissions
+            .filter(object.eq_any(&assigns))
+            .select(subject)
+            .distinct()
+            .load::<i32>(conn)?;
+        let projects = tasks.filter(id.eq_any(&involved)).select(project).distinct().load::<Option<i32>>(conn)?;
+        recipients.extend(project_members
+            .filter(crate::schema::project_members::project.nullable().eq_any(projects))
+            .select(member)
+            .load::<i32>(conn)?);
+        recipients.sort();
+        recipients.dedup();
+
+        Ok(Self {
+            event: event,
+            recipients: recipients,
+            data: Data {
+                tasks: ids.clone(),
+                arrows: arrows.clone(),
+            },
+        })
+    }
+}
+
+impl std::fmt::Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "event: {}\ndata: {}\n\n",
+            self.event.as_str(),
+            serde_json::to_string(&self.data).map_err(|_| std::fmt::Error)?,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    fn message(recipients: Vec<i32>) -> Message {
+        Message {
+            event: webhook::Event::TaskArchived,
+            recipients: recipients,
+            data: Data {
+                tasks: vec![3, 4],
+                arrows: vec![models::Arrow { source: 3, target: 4 }],
+            },
+        }
+    }
+    #[test]
+    fn t_format() {
+        assert_eq!(
+            message(vec![1]).to_string(),
+            "event: task.archived\ndata: {\"tasks\":[3,4],\"arrows\":[{\"source\":3,\"target\":4}]}\n\n",
+        );
+    }
+    #[test]
+    fn t_send() {
+        let broadcaster = Broadcaster::default();
+        let mut one = broadcaster.subscribe(1);
+        let two = broadcaster.subscribe(2);
+        drop(two);
+        broadcaster.send(&message(vec![1, 2]));
+        assert_eq!(broadcaster.clients.lock().unwrap().len(), 1);
+        let received = futures::executor::block_on(async {
+            vec![one.next().await.unwrap(), one.next().await.unwrap()]
+        });
+        assert_eq!(received[0], Bytes::from_static(b"retry: 3000\n\n"));
+        assert_eq!(received[1], Bytes::from(message(vec![1]).to_string()));
+        broadcaster.send(&message(vec![2]));
+        broadcaster.ping();
+        assert_eq!(futures::executor::block_on(one.next()), Some(Bytes::from_static(b": ping\n\n")));
+    }
+}
@@ -0,0 +1,82 @@
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::models;
+
+// sorted home per user, invalidated on task/arrow/allocation changes, user renames, and project or membership changes
+#[derive(Default)]
+pub struct Cache {
+    homes: Mutex<Homes>,
+}
+
+#[derive(Default)]
+struct Homes {
+    generation: u64, // bumped on each invalidation
+    entries: HashMap<i32, Entry>,
+}
+
+struct Entry {
+    tz: Tz,
+    at: DateTime<Utc>,
+    tasks: Vec<models::ResTask>,
+}
+
+impl Cache {
+    // priority and schedule drift with the clock even without changes
+    fn ttl() -> Duration {
+        Duration::minutes(1)
+    }
+    pub fn get(&self, user: &models::AuthedUser) -> Option<Vec<models::ResTask>> {
+        let homes = self.homes.lock().unwrap();
+        homes.entries.get(&user.id)
+        .filter(|entry| entry.tz == user.tz)
+        .filter(|entry| Utc::now() < entry.at + Self::ttl())
+        .map(|entry| entry.tasks.clone())
+    }
+    // to be read before loading what is to be set
+    pub fn generation(&self) -> u64 {
+        self.homes.lock().unwrap().generation
+    }
+    // skipped if invalidated since `generation`, not to keep what was loaded before a change
+    pub fn set(&self, user: &models::AuthedUser, tasks: &Vec<models::ResTask>, generation: u64) {
+        let mut homes = self.homes.lock().unwrap();
+        if homes.generation != generation {
+            return
+        }
+        homes.entries.insert(user.id, Entry {
+            tz: user.tz,
+            at: Utc::now(),
+            tasks: tasks.clone(),
+        });
+    }
+    // tasks are shared through permissions, so drop everyone's
+    pub fn invalidate(&self) {
+        let mut homes = self.homes.lock().unwrap();
+        homes.generation += 1;
+        homes.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_set() {
+        let cache = Cache::default();
+        let user = models::AuthedUser {
+            id: 1,
+            tz: Tz::UTC,
+            session: None,
+            scope: models::Scope::Session,
+        };
+        let stale = cache.generation();
+        cache.invalidate(); // a write while loading
+        cache.set(&user, &vec![models::ResTask::default()], stale);
+        assert!(cache.get(&user).is_none());
+        cache.set(&user, &vec![models::ResTask::default()], cache.generation());
+        assert_eq!(cache.get(&user).map(|ts| ts.len()), Some(1));
+    }
+}
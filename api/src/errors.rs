@@ -1,27 +1,212 @@
-use actix_web::{error::{BlockingError, ResponseError}, http::StatusCode, HttpResponse};
-use combine::error::StringStreamError;
+use actix_web::{error::{BlockingError, ResponseError}, http::{header, StatusCode}, HttpResponse};
+use combine::{easy, stream::position::SourcePosition};
 use derive_more::Display;
 use diesel::result::{DatabaseErrorKind, Error as DbError};
+use serde::Serialize;
 
 #[derive(Debug, Display)]
 pub enum ServiceError {
-    BadRequest(String),
+    BadRequest(ErrorBody),
     Unauthorized,
+    Forbidden,
+    // seconds to retry after
+    TooManyRequests(i64),
     InternalServerError,
 }
 
+// clients should branch on `code`, not on `message`
+#[derive(Debug, Display, Serialize)]
+#[display(fmt = "{}", message)]
+pub struct ErrorBody {
+    pub code: Code,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub syntax: Vec<Syntax>,
+}
+
+// where the text parser got stuck, for the input area to underline
+#[derive(Debug, Display, Serialize, PartialEq)]
+#[display(fmt = "L{}:{} {}", line, column, "self.describe()")]
+pub struct Syntax {
+    pub line: usize,
+    pub column: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unexpected: Option<String>,
+    pub expected: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Code {
+    AlreadyInUse,
+    DeadlineBeforeStartable,
+    DuplicateId,
+    EmptyComment,
+    ExistingNodesWiring,
+    Forbidden,
+    InternalServerError,
+    InvalidAllocation,
+    InvalidDatetime,
+    InvalidEmail,
+    InvalidName,
+    InvalidUrl,
+    InvitationExpired,
+    InvitationInvalid,
+    Loop,
+    NoDestination,
+    NoEditPermission,
+    NoTitle,
+    NotFound,
+    NotPreviewable,
+    OtpRequired,
+    OutOfRange,
+    PasswordMismatch,
+    PasswordTooShort,
+    Regex,
+    Syntax,
+    Teapot,
+    TooHeavy,
+    TooLarge,
+    TooManyRequests,
+    Unauthorized,
+    UnknownEvent,
+    UnsupportedType,
+    UserExists,
+    UserNotFound,
+    WrongOtp,
+    WrongPassword,
+}
+
+impl ErrorBody {
+    fn new(code: Code, message: &str) -> Self {
+        Self {
+            code: code,
+            message: message.into(),
+            line: None,
+            task_id: None,
+            field: None,
+            syntax: Vec::new(),
+        }
+    }
+}
+
+impl Syntax {
+    fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(unexpected) = &self.unexpected {
+            parts.push(format!("unexpected {}", unexpected))
+        }
+        if !self.expected.is_empty() {
+            parts.push(format!("expected {}", self.expected.join(" or ")))
+        }
+        parts.extend(self.messages.iter().cloned());
+        if parts.is_empty() {
+            parts.push("syntax error".into())
+        }
+        format!("{}.", parts.join(", "))
+    }
+}
+
+impl From<easy::Errors<char, &str, SourcePosition>> for Syntax {
+    fn from(errors: easy::Errors<char, &str, SourcePosition>) -> Self {
+        // tokens in backquotes, descriptions as they are
+        let quote = |info: &easy::Info<char, &str>| match info {
+            easy::Info::Token(c) => format!("`{}`", c.escape_default()),
+            easy::Info::Range(r) => format!("`{}`", r.escape_default()),
+            easy::Info::Owned(s) => s.clone(),
+            easy::Info::Static(s) => s.to_string(),
+        };
+        let mut syntax = Self {
+            line: errors.position.line as usize,
+            column: errors.position.column as usize,
+            unexpected: None,
+            expected: Vec::new(),
+            messages: Vec::new(),
+        };
+        for error in &errors.errors {
+            match error {
+                easy::Error::Unexpected(info) => syntax.unexpected = Some(quote(info)),
+                easy::Error::Expected(info) => {
+                    let expected = quote(info);
+                    if !syntax.expected.contains(&expected) {
+                        syntax.expected.push(expected)
+                    }
+                },
+                easy::Error::Message(info) => syntax.messages.push(quote(info)),
+                easy::Error::Other(e) => syntax.messages.push(e.to_string()),
+            }
+        }
+        syntax
+    }
+}
+
+impl ServiceError {
+    pub fn bad_request(code: Code, message: impl Into<String>) -> Self {
+        Self::BadRequest(ErrorBody::new(code, &message.into()))
+    }
+    pub fn syntax(syntax: Vec<Syntax>) -> Self {
+        let message = syntax.iter().map(|s| s.to_string()).collect::<Vec<String>>().join(" ");
+        let line = syntax.first().map(|s| s.line);
+        let mut body = ErrorBody::new(Code::Syntax, &message);
+        body.line = line;
+        body.syntax = syntax;
+        Self::BadRequest(body)
+    }
+    pub fn line(mut self, line: usize) -> Self {
+        if let Self::BadRequest(body) = &mut self {
+            body.line = Some(line)
+        }
+        self
+    }
+    pub fn task_id(mut self, task_id: i32) -> Self {
+        if let Self::BadRequest(body) = &mut self {
+            body.task_id = Some(task_id)
+        }
+        self
+    }
+    pub fn field(mut self, field: &str) -> Self {
+        if let Self::BadRequest(body) = &mut self {
+            body.field = Some(field.into())
+        }
+        self
+    }
+}
+
 impl ResponseError for ServiceError {
     fn error_response(&self) -> HttpResponse {
         match self {
-            ServiceError::BadRequest(msg)     => HttpResponse::BadRequest().body(msg),
-            ServiceError::Unauthorized        => HttpResponse::Unauthorized().finish(),
-            ServiceError::InternalServerError => HttpResponse::InternalServerError().finish(),
+            ServiceError::BadRequest(body)    => HttpResponse::BadRequest().json(body),
+            ServiceError::Unauthorized        => HttpResponse::Unauthorized().json(
+                ErrorBody::new(Code::Unauthorized, "authentication required.")
+            ),
+            ServiceError::Forbidden           => HttpResponse::Forbidden().json(
+                ErrorBody::new(Code::Forbidden, "not allowed with this credential.")
+            ),
+            ServiceError::TooManyRequests(retry) => HttpResponse::TooManyRequests()
+                .header(header::RETRY_AFTER, retry.to_string())
+                .json(ErrorBody::new(Code::TooManyRequests, &format!(
+                    "too many attempts, retry after {} seconds.",
+                    retry,
+                ))),
+            ServiceError::InternalServerError => HttpResponse::InternalServerError().json(
+                ErrorBody::new(Code::InternalServerError, "something went wrong.")
+            ),
         }
     }
     fn status_code(&self) -> StatusCode {
         match self {
             ServiceError::BadRequest(_)       => StatusCode::BAD_REQUEST,
             ServiceError::Unauthorized        => StatusCode::UNAUTHORIZED,
+            ServiceError::Forbidden           => StatusCode::FORBIDDEN,
+            ServiceError::TooManyRequests(_)  => StatusCode::TOO_MANY_REQUESTS,
             ServiceError::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -43,7 +228,16 @@ impl From<DbError> for ServiceError {
         match error {
             DbError::DatabaseError(kind, info) => {
                 if let DatabaseErrorKind::UniqueViolation = kind {
-                    return Self::BadRequest(info.message().into());
+                    // not to leak raw messages, tell only the column of constraints like users_email_key
+                    // or project_members_project_member_key, stripping the table name as it may contain '_'
+                    let error = Self::bad_request(Code::AlreadyInUse, "already in use.");
+                    return match info.constraint_name().zip(info.table_name())
+                    .and_then(|(c, t)| c.strip_prefix(t))
+                    .and_then(|c| c.strip_prefix('_'))
+                    .and_then(|c| c.strip_suffix("_key")) {
+                        Some(field) => error.field(field),
+                        None => error,
+                    }
                 }
                 Self::InternalServerError
             }
@@ -56,11 +250,11 @@ impl From<regex::Error> for ServiceError {
     fn from(error: regex::Error) -> Self {
         dbg!(&error);
         match error {
-            regex::Error::Syntax(s) => Self::BadRequest(format!(
+            regex::Error::Syntax(s) => Self::bad_request(Code::Regex, format!(
                 "regex error: {}",
                 s,
             )),
-            regex::Error::CompiledTooBig(_) => Self::BadRequest(format!(
+            regex::Error::CompiledTooBig(_) => Self::bad_request(Code::Regex, format!(
                 "regex compiled too big."
             )),
             _ => Self::InternalServerError,
@@ -68,14 +262,35 @@ impl From<regex::Error> for ServiceError {
     }
 }
 
-impl From<StringStreamError> for ServiceError {
-    fn from(error: StringStreamError) -> Self {
-        dbg!(&error);
-        match error {
-            StringStreamError::UnexpectedParse => Self::BadRequest(format!(
-                "there seems to be a syntax error.",
-            )),
-            _ => Self::InternalServerError,
+#[cfg(test)]
+mod tests {
+    use diesel::result::DatabaseErrorInformation;
+
+    use super::*;
+
+    struct Info(&'static str, &'static str);
+
+    impl DatabaseErrorInformation for Info {
+        fn message(&self) -> &str { "duplicate key value violates unique constraint" }
+        fn details(&self) -> Option<&str> { None }
+        fn hint(&self) -> Option<&str> { None }
+        fn table_name(&self) -> Option<&str> { Some(self.0) }
+        fn column_name(&self) -> Option<&str> { None }
+        fn constraint_name(&self) -> Option<&str> { Some(self.1) }
+    }
+
+    fn field(table: &'static str, constraint: &'static str) -> Option<String> {
+        match ServiceError::from(DbError::DatabaseError(DatabaseErrorKind::UniqueViolation, Box::new(Info(table, constraint)))) {
+            ServiceError::BadRequest(e) => e.field,
+            _ => panic!(),
         }
     }
+
+    #[test]
+    fn t_unique_violation_field() {
+        assert_eq!(field("users", "users_email_key"), Some(String::from("email")));
+        assert_eq!(field("project_members", "project_members_project_member_key"), Some(String::from("project_member")));
+        assert_eq!(field("webhook_deliveries", "webhook_deliveries_webhook_event_key"), Some(String::from("webhook_event")));
+        assert_eq!(field("users", "other_name_key"), None);
+    }
 }
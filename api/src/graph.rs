@@ -0,0 +1,337 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::models::{Arrows, LR};
+
+// walks the task DAG in topological order, never enumerating paths
+pub struct Graph {
+    nodes: Vec<i32>,
+    to_root: HashMap<i32, Vec<i32>>,
+    to_leaf: HashMap<i32, Vec<i32>>,
+}
+
+impl From<&Arrows> for Graph {
+    fn from(arrows: &Arrows) -> Self {
+        Self::new(&Vec::new(), arrows)
+    }
+}
+
+impl Graph {
+    // isolated nodes can be given besides the arrows' ones
+    pub fn new(nodes: &Vec<i32>, arrows: &Arrows) -> Self {
+        let mut nodes = nodes.iter().copied().chain(arrows.nodes()).collect::<Vec<i32>>();
+        nodes.sort();
+        nodes.dedup();
+        Self {
+            nodes: nodes,
+            to_root: arrows.map_to(LR::Root),
+            to_leaf: arrows.map_to(LR::Leaf),
+        }
+    }
+    fn next(&self, lr: LR, id: i32) -> &[i32] {
+        let map = match lr {
+            LR::Leaf => &self.to_leaf,
+            LR::Root => &self.to_root,
+        };
+        map.get(&id).map(|v| v.as_slice()).unwrap_or_default()
+    }
+    // Kahn's algorithm from leaves to roots, leaving out nodes on or behind a loop
+    pub fn sorted(&self) -> Vec<i32> {
+        let mut degrees = self.nodes.iter()
+        .map(|id| (*id, self.next(LR::Leaf, *id).len()))
+        .collect::<HashMap<i32, usize>>();
+        let mut queue = self.nodes.iter().copied()
+        .filter(|id| degrees[id] == 0)
+        .collect::<VecDeque<i32>>();
+        let mut sorted = Vec::new();
+        while let Some(id) = queue.pop_front() {
+            sorted.push(id);
+            for next in self.next(LR::Root, id) {
+                let degree = degrees.get_mut(next).unwrap();
+                *degree -= 1;
+                if *degree == 0 {
+                    queue.push_back(*next)
+                }
+            }
+        }
+        sorted
+    }
+    // one loop in the direction of arrows, if any
+    pub fn cycle(&self) -> Option<Vec<i32>> {
+        let sorted = self.sorted();
+        let remains = self.nodes.iter().copied()
+        .filter(|id| !sorted.contains(id))
+        .collect::<Vec<i32>>();
+        // every remaining node has a remaining predecessor, so walking back must loop
+        let mut walk = vec![*remains.first()?];
+        loop {
+            let last = *walk.last().unwrap();
+            let prev = *self.next(LR::Leaf, last).iter().find(|id| remains.contains(id)).unwrap();
+            if let Some(pos) = walk.iter().position(|id| *id == prev) {
+                let mut cycle = walk.split_off(pos);
+                cycle.reverse();
+                let first = cycle.iter().enumerate().min_by_key(|(_, id)| **id).unwrap().0;
+                cycle.rotate_left(first);
+                return Some(cycle)
+            }
+            walk.push(prev);
+        }
+    }
+    // nodes reachable from `ids` toward `lr`, including themselves
+    pub fn nodes_to(&self, lr: LR, ids: &Vec<i32>) -> Vec<i32> {
+        let mut visited: HashSet<i32> = ids.iter().cloned().collect();
+        let mut stack = ids.clone();
+        while let Some(id) = stack.pop() {
+            for next in self.next(lr, id) {
+                if visited.insert(*next) {
+                    stack.push(*next);
+                }
+            }
+        }
+        let mut nodes: Vec<i32> = visited.into_iter().collect();
+        nodes.sort();
+        nodes
+    }
+    // nodes reachable from `id` toward `lr` within `depth` steps, each with one of the shortest paths from `id`
+    pub fn paths_from(&self, lr: LR, id: i32, depth: Option<usize>) -> Vec<(i32, Vec<i32>)> {
+        let mut paths: HashMap<i32, Vec<i32>> = HashMap::new();
+        paths.insert(id, vec![id]);
+        let mut queue = VecDeque::new();
+        queue.push_back(id);
+        while let Some(id) = queue.pop_front() {
+            let path = paths[&id].clone();
+            if depth.map_or(false, |d| d < path.len()) {
+                continue
+            }
+            for next in self.next(lr, id) {
+                if !paths.contains_key(next) {
+                    let mut path = path.clone();
+                    path.push(*next);
+                    paths.insert(*next, path);
+                    queue.push_back(*next);
+                }
+            }
+        }
+        paths.remove(&id);
+        let mut paths = paths.into_iter().collect::<Vec<(i32, Vec<i32>)>>();
+        paths.sort_by_key(|(id, path)| (path.len(), *id));
+        paths
+    }
+    // latest start to meet every direct or indirect deadline, None if no deadline ahead
+    pub fn latests<D, W>(&self, deadline: D, weight: W) -> HashMap<i32, Option<i64>>
+    where
+        D: Fn(i32) -> Option<i64>,
+        W: Fn(i32) -> i64,
+    {
+        let mut latests: HashMap<i32, Option<i64>> = HashMap::new();
+        for id in self.sorted().into_iter().rev() {
+            let bound = self.next(LR::Root, id).iter()
+            .filter_map(|next| latests.get(next).copied().flatten())
+            .chain(deadline(id))
+            .min();
+            latests.insert(id, bound.map(|b| b - weight(id)));
+        }
+        latests
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::min;
+    use crate::models::Arrow;
+
+    type Path = Vec<i32>;
+
+    // former exhaustive path enumeration, kept as the oracle
+    fn paths_to(id: i32, lr: LR, arrows: &Arrows) -> Vec<Path> {
+        let map = arrows.map_to(lr);
+        let mut results: Vec<Path> = Vec::new();
+        let mut remains: Vec<i32> = Vec::new();
+        let mut re_map: HashMap<i32, Vec<i32>> = HashMap::new();
+        let mut cursor = id;
+        let mut path: Vec<i32> = Vec::new();
+        'main: loop {
+            if path.contains(&cursor) { // got cycle instead of path
+                results.clear();
+                break
+            }
+            path.push(cursor);
+            if let Some(destinations) = map.get(&cursor) {
+                let mut destinations = destinations.clone();
+                if let Some(dest) = destinations.pop() {
+                    remains.push(cursor);
+                    re_map.insert(cursor, destinations);
+                    cursor = dest;
+                    continue
+                }
+            }
+            results.push(Path::from(path.clone()));
+            while let Some(rem) = remains.pop() {
+                while cursor != rem {
+                    cursor = path.pop().unwrap();
+                }
+                path.push(cursor);
+                if let Some(dest) = re_map.get_mut(&cursor).unwrap().pop() {
+                    remains.push(cursor);
+                    cursor = dest;
+                    continue 'main
+                }
+            }
+            break
+        }
+        results
+    }
+    fn list(lr: LR, arrows: &Arrows) -> Vec<i32> {
+        arrows.nodes().into_iter().filter(|id| crate::models::Tid::from(*id).is(lr, arrows)).collect()
+    }
+    fn has_cycle(arrows: &Arrows) -> bool {
+        if arrows.arrows.is_empty() {
+            return false
+        }
+        if list(LR::Leaf, arrows).is_empty() || list(LR::Root, arrows).is_empty() {
+            return true
+        }
+        list(LR::Leaf, arrows).iter().any(|leaf| paths_to(*leaf, LR::Root, arrows).is_empty())
+    }
+    fn nodes_to(id: i32, lr: LR, arrows: &Arrows) -> Vec<i32> {
+        let mut nodes = paths_to(id, lr, arrows).into_iter().flatten().collect::<Vec<i32>>();
+        nodes.sort();
+        nodes.dedup();
+        nodes
+    }
+    fn latest(id: i32, arrows: &Arrows, deadlines: &HashMap<i32, i64>) -> Option<i64> {
+        paths_to(id, LR::Root, arrows).iter().filter_map(|path| {
+            let mut path = path.clone();
+            while let Some(last) = path.pop() {
+                if deadlines.contains_key(&last) {
+                    path.push(last);
+                    break
+                }
+            }
+            let mut cursor = i64::MAX;
+            for id in path.iter().rev() {
+                if let Some(deadline) = deadlines.get(id) {
+                    cursor = min(cursor, *deadline)
+                }
+                cursor -= weight(*id)
+            }
+            if cursor == i64::MAX { None } else { Some(cursor) }
+        }).min()
+    }
+    fn weight(id: i32) -> i64 {
+        (id as i64 % 3 + 1) * 60
+    }
+    // small random graphs from a fixed seed
+    fn samples(acyclic: bool) -> Vec<Arrows> {
+        let mut seed: u64 = 20210121;
+        let mut rand = move |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % n
+        };
+        (0..300).map(|_| {
+            let mut arrows = Vec::new();
+            for _ in 0..rand(12) {
+                let (s, t) = (rand(8) as i32, rand(8) as i32);
+                if s == t || (acyclic && t < s) { continue }
+                let arrow = Arrow { source: s, target: t };
+                if !arrows.contains(&arrow) {
+                    arrows.push(arrow)
+                }
+            }
+            Arrows::from(arrows)
+        }).collect()
+    }
+    #[test]
+    fn t_has_cycle() {
+        for arrows in samples(false) {
+            // the oracle misses loops that no leaf leads to
+            if has_cycle(&arrows) {
+                assert!(Graph::from(&arrows).cycle().is_some(), "{:?}", arrows);
+            }
+        }
+        for arrows in samples(true) {
+            assert!(!Graph::from(&arrows).cycle().is_some(), "{:?}", arrows);
+        }
+        let arrows = Arrows::from(vec![
+            Arrow { source: 0, target: 1 },
+            Arrow { source: 2, target: 3 },
+            Arrow { source: 3, target: 2 },
+        ]);
+        assert!(!has_cycle(&arrows));
+        assert!(Graph::from(&arrows).cycle().is_some());
+    }
+    #[test]
+    fn t_cycle() {
+        for arrows in samples(false) {
+            let graph = Graph::from(&arrows);
+            match graph.cycle() {
+                None => assert_eq!(graph.sorted().len(), graph.nodes.len()),
+                Some(cycle) => {
+                    for (i, id) in cycle.iter().enumerate() {
+                        let next = cycle[(i + 1) % cycle.len()];
+                        assert!(arrows.arrows.contains(&Arrow { source: *id, target: next }), "{:?}", arrows);
+                    }
+                },
+            }
+        }
+        let arrows = Arrows::from(vec![
+            Arrow { source: 0, target: 1 },
+            Arrow { source: 1, target: 2 },
+            Arrow { source: 2, target: 3 },
+            Arrow { source: 3, target: 1 },
+        ]);
+        assert_eq!(Graph::from(&arrows).cycle(), Some(vec![1, 2, 3]));
+    }
+    #[test]
+    fn t_nodes_to() {
+        for arrows in samples(true) {
+            let graph = Graph::from(&arrows);
+            for id in arrows.nodes() {
+                for lr in vec![LR::Leaf, LR::Root] {
+                    assert_eq!(graph.nodes_to(lr, &vec![id]), nodes_to(id, lr, &arrows), "{:?}", arrows);
+                }
+            }
+        }
+    }
+    #[test]
+    fn t_paths_from() {
+        for arrows in samples(true) {
+            let graph = Graph::from(&arrows);
+            for id in arrows.nodes() {
+                for lr in vec![LR::Leaf, LR::Root] {
+                    let oracle = paths_to(id, lr, &arrows);
+                    let paths = graph.paths_from(lr, id, None);
+                    let mut nodes = paths.iter().map(|(node, _)| *node).chain(Some(id)).collect::<Vec<i32>>();
+                    nodes.sort();
+                    assert_eq!(nodes, nodes_to(id, lr, &arrows), "{:?}", arrows);
+                    for (node, path) in &paths {
+                        let distance = oracle.iter().filter_map(|p| p.iter().position(|n| n == node)).min();
+                        assert_eq!(Some(path.len() - 1), distance, "{:?}", arrows);
+                        assert_eq!((path[0], path[path.len() - 1]), (id, *node));
+                        for step in path.windows(2) {
+                            let (prev, next) = (step[0], step[1]);
+                            assert!(graph.next(lr, prev).contains(&next), "{:?}", arrows);
+                        }
+                    }
+                    for depth in 0..3 {
+                        let limited = graph.paths_from(lr, id, Some(depth));
+                        assert_eq!(limited, paths.iter().filter(|(_, p)| p.len() <= depth + 1).cloned().collect::<Vec<_>>());
+                    }
+                }
+            }
+        }
+    }
+    #[test]
+    fn t_latests() {
+        for (i, arrows) in samples(true).into_iter().enumerate() {
+            let deadlines = arrows.nodes().into_iter()
+            .filter(|id| (*id as usize + i) % 3 == 0)
+            .map(|id| (id, id as i64 * 100))
+            .collect::<HashMap<i32, i64>>();
+            let latests = Graph::from(&arrows).latests(|id| deadlines.get(&id).copied(), weight);
+            for id in arrows.nodes() {
+                assert_eq!(latests[&id], latest(id, &arrows, &deadlines), "{:?}", arrows);
+            }
+        }
+    }
+}
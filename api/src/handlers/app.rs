@@ -1,6 +1,18 @@
+pub mod attachments;
+pub mod comments;
+pub mod events;
 pub mod exec;
 pub mod focus;
 pub mod home;
+pub mod links;
+pub mod milestones;
+pub mod projects;
+pub mod reminder;
+pub mod sessions;
 pub mod star;
+pub mod stats;
 pub mod text;
+pub mod tokens;
+pub mod totp;
+pub mod webhooks;
 mod _parser;
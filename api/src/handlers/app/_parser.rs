@@ -1,5 +1,5 @@
 use combine::{
-    Parser, Stream, attempt, choice, eof, from_str, many, many1,
+    EasyParser, Stream, attempt, choice, eof, from_str, many, many1,
     optional, parser, satisfy, sep_by1, skip_many, skip_many1, token,
 };
 use combine::parser::{
@@ -7,6 +7,7 @@ use combine::parser::{
     combinator::recognize,
     repeat::{skip_count_min_max, take_until},
 };
+use combine::stream::position;
 use std::str::FromStr;
 
 use crate::errors;
@@ -17,46 +18,116 @@ impl FromStr for Req {
     type Err = errors::ServiceError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let req = req_().parse(s)?.0;
-        if let Req::Tasks(ts) = &req {
+        read(s).map_err(errors::ServiceError::syntax)?.valid()
+    }
+}
+
+// lines and columns count in `s`
+fn read(s: &str) -> Result<Req, Vec<errors::Syntax>> {
+    match req_().easy_parse(position::Stream::new(s)) {
+        Ok((req, _)) => Ok(req),
+        Err(e) => {
+            let mut syntax = vec![errors::Syntax::from(e)];
+            // resume tasks at the next line, to report every broken line at once
+            if !s.starts_with('/') {
+                let lines = s.lines().collect::<Vec<&str>>();
+                loop {
+                    let offset = syntax.last().unwrap().line;
+                    if lines.len() <= offset { break }
+                    let rest = lines[offset..].join("\n");
+                    match many::<Vec<ReqTask>, _, _>(req_task_()).easy_parse(position::Stream::new(&*rest)) {
+                        Ok(_) => break,
+                        Err(e) => {
+                            let mut next = errors::Syntax::from(e);
+                            next.line += offset;
+                            syntax.push(next)
+                        },
+                    }
+                }
+            }
+            Err(syntax)
+        },
+    }
+}
+
+impl Req {
+    fn valid(self) -> Result<Self, errors::ServiceError> {
+        if let Req::Tasks(ts) = &self {
             if ts.tasks.iter().any(|t| t.attribute.title.is_empty()) {
-                return Err(Self::Err::BadRequest("there is a item with no title.".into()))
+                return Err(errors::ServiceError::bad_request(errors::Code::NoTitle, "there is a item with no title."))
             }
             if ts.tasks.iter().filter_map(|t| t.attribute.weight).any(|w| !(w < 10_000.)) {
-                return Err(Self::Err::BadRequest("there is a too heavy item.".into()))
+                return Err(errors::ServiceError::bad_request(errors::Code::TooHeavy, "there is a too heavy item.").field("weight"))
             }
         }
-        Ok(req)
+        Ok(self)
     }
 }
 
 impl text::ReqBody {
-    pub fn wash(&self) -> String {
+    // errors point to lines of the submitted text
+    pub fn parse(&self) -> Result<Req, errors::ServiceError> {
+        let lines = self.washed_lines();
+        let washed = lines.iter().map(|(_, s)| s.as_str()).collect::<Vec<&str>>().join("\n");
+        read(&washed).map_err(|mut syntax| {
+            for s in &mut syntax {
+                if let Some((origin, _)) = lines.get(s.line - 1).or_else(|| lines.last()) {
+                    s.line = *origin
+                }
+            }
+            errors::ServiceError::syntax(syntax)
+        })?.valid()
+    }
+    #[cfg(test)]
+    fn wash(&self) -> String {
         self
-        .remove_comments()
-        .trim()
-        .lines()
-        .map(|s| s.trim_end().to_string())
-        .filter(|s| !s.is_empty())
+        .washed_lines()
+        .into_iter()
+        .map(|(_, s)| s)
         .collect::<Vec<String>>()
         .join("\n")
     }
+    // each washed line with its original line number
+    pub fn washed_lines(&self) -> Vec<(usize, String)> {
+        let (text, origins) = self.strip_comments();
+        let mut lines = text
+        .lines()
+        .zip(origins)
+        .map(|(s, origin)| (origin, s.trim_end().to_string()))
+        .filter(|(_, s)| !s.is_empty())
+        .collect::<Vec<(usize, String)>>();
+        if let Some((_, first)) = lines.first_mut() {
+            *first = first.trim_start().to_string();
+        }
+        lines
+    }
+    #[cfg(test)]
     fn remove_comments(&self) -> String {
+        self.strip_comments().0
+    }
+    fn strip_comments(&self) -> (String, Vec<usize>) {
         let prefix = "<!--";
         let suffix = "-->";
         let mut src = &*self.text;
         let mut res = String::new();
+        let mut line = 1;
+        let mut origins = vec![line];
         loop {
             let cursor = src.find(prefix).unwrap_or_else(|| src.len());
             let pair = src.split_at(cursor);
+            for _ in pair.0.matches('\n') {
+                line += 1;
+                origins.push(line);
+            }
             res.push_str(pair.0);
             src = pair.1;
             let cursor = src.find(suffix).map(|cur| cur + suffix.len()).unwrap_or_else(|| src.len());
             let pair = src.split_at(cursor);
+            line += pair.0.matches('\n').count();
             src = pair.1;
             if src.is_empty() { break }
         }
-        res
+        (res, origins)
     }
 }
 
@@ -67,8 +138,9 @@ parser! {
             token('/').with(optional(req_command_())).map(|opt| {
                 Req::Command(opt.unwrap_or(ReqCommand::Help))
             }),
-            many(req_task_()).map(|ts| {
+            optional(attempt(project_line_())).and(many(req_task_())).map(|(project, ts)| {
                 Req::Tasks(ReqTasks {
+                    project: project,
                     tasks: ts,
                 })
             }),
@@ -82,18 +154,19 @@ parser! {
             token('u').with(optional(spaces1_().with(req_user_()))).map(|opt| {
                 ReqCommand::User(opt.unwrap_or(ReqUser::Info))
             }),
+            attempt(string("stats")).map(|_| ReqCommand::Stats),
             token('s').with(conditions_()).map(|x| {
                 ReqCommand::Search(x)
             }),
             string("tutorial").map(|_| ReqCommand::Tutorial),
             string("coffee").map(|_| ReqCommand::Coffee),
-        ))
+        )).expected("command `u`, `s`, `stats` or `tutorial`")
     }
 }
 parser! {
     fn req_user_[Input]()(Input) -> ReqUser
     where [ Input: Stream<Token = char> ] {
-        token('-').with(req_modify_()).map(|x| ReqUser::Modify(x))
+        token('-').expected("`-` to modify").with(req_modify_()).map(|x| ReqUser::Modify(x))
     }
 }
 parser! {
@@ -105,15 +178,15 @@ parser! {
             token('n').with(spaces1_().with(ascii_graphics1_())).map(|x| ReqModify::Name(x)),
             token('t').with(spaces1_().with(timescale_())).map(|x| ReqModify::Timescale(x)),
             token('a').with(many(spaces1_().with(req_allocation_()))).map(|x| ReqModify::Allocations(x)),
-        ))
+        )).expected("option `e`, `p`, `n`, `t` or `a`")
     }
 }
 parser! {
     fn password_set_[Input]()(Input) -> PasswordSet
     where [ Input: Stream<Token = char> ] {
-        ascii_graphics1_().skip(spaces1_())
-        .and(ascii_graphics1_()).skip(spaces1_())
-        .and(ascii_graphics1_())
+        ascii_graphics1_().expected("current password")
+        .and(spaces1_().with(ascii_graphics1_()).expected("new password"))
+        .and(spaces1_().with(ascii_graphics1_()).expected("confirmation of new password"))
         .map(|((o, n), c)| PasswordSet {
             old: o,
             new: n,
@@ -136,13 +209,18 @@ parser! {
             p(Timescale::Minutes),
             p(Timescale::Minute),
             p(Timescale::Second),
-        ))
+        )).expected("timescale `Y`, `Q`, `M`, `W`, `D`, `6h`, `h`, `15m`, `m` or `s`")
     }
 }
 parser! {
     fn req_allocation_[Input]()(Input) -> ReqAllocation
     where [ Input: Stream<Token = char> ] {
-        non_nega_i_().skip(token(':')).and(non_nega_i_()).skip(token('-')).and(non_nega_i_()).skip(token('h'))
+        non_nega_i_().expected("allocation open hour")
+        .skip(token(':').expected("`:` after allocation open hour"))
+        .and(non_nega_i_().expected("allocation open minute"))
+        .skip(token('-').expected("`-` after allocation open time"))
+        .and(non_nega_i_().expected("allocation hours"))
+        .skip(token('h').expected("`h` after allocation hours"))
         .map(|((open_h, open_m), hours)| ReqAllocation {
             open_h: open_h,
             open_m: open_m,
@@ -185,6 +263,7 @@ parser! {
                     token('d'),
                     token('c'),
                     token('u'),
+                    token('a'),
                 ])).and(optional(token('<').with(datetime_())))
             ).map(|((l, c), r)| {
                 let mut condition = Condition::default();
@@ -193,16 +272,19 @@ parser! {
                     'd' => condition.deadline = (l, r),
                     'c' => condition.created_at = (l, r),
                     'u' => condition.updated_at = (l, r),
+                    'a' => condition.archived_at = (l, r),
                     _ => unreachable!()
                 }
                 condition
             }),
-            attempt(optional(token('@').or(token('&'))).and(expression_())).map(|(opt, expr)| {
+            attempt(optional(token('@').or(token('&')).or(token('>')).or(token('%'))).and(expression_())).map(|(opt, expr)| {
                 let mut condition = Condition::default();
                 match opt {
                     None => condition.title = Some(expr),
                     Some('@') => condition.assign = Some(expr),
                     Some('&') => condition.link = Some(expr),
+                    Some('>') => condition.note = Some(expr),
+                    Some('%') => condition.project = Some(expr),
                     _ => unreachable!()
                 }
                 condition
@@ -221,9 +303,12 @@ impl std::iter::Extend<Self> for Condition {
             if self.deadline.lt(&item.deadline) { self.deadline = item.deadline };
             if self.created_at.lt(&item.created_at) { self.created_at = item.created_at };
             if self.updated_at.lt(&item.updated_at) { self.updated_at = item.updated_at };
+            if self.archived_at.lt(&item.archived_at) { self.archived_at = item.archived_at };
             if self.title.lt(&item.title) { self.title = item.title };
             if self.assign.lt(&item.assign) { self.assign = item.assign };
             if self.link.lt(&item.link) { self.link = item.link };
+            if self.note.lt(&item.note) { self.note = item.note };
+            if self.project.lt(&item.project) { self.project = item.project };
         }
     }
 }
@@ -347,15 +432,17 @@ parser! {
     where [ Input: Stream<Token = char> ] {
         indents_()
         .and(attributes1_())
-        .and(optional(attempt(newline().with(inline_spaces_().with(link_())))))
+        .and(many::<Vec<ReqLink>, _, _>(attempt(newline().with(inline_spaces_().with(labeled_link_())))))
+        .and(many::<Vec<String>, _, _>(attempt(newline().with(inline_spaces_().with(note_line_())))))
         .skip(choice((
             newline().map(|_| ()),
             eof(),
         )))
-        .map(|((indent, attribute), link)| ReqTask {
+        .map(|(((indent, attribute), links), note)| ReqTask {
             indent: indent,
             attribute: attribute,
-            link: link,
+            links: links,
+            note: if note.is_empty() { None } else { Some(note.join("\n")) },
         })
     }
 }
@@ -372,7 +459,7 @@ parser! {
     where [ Input: Stream<Token = char> ] {
         choice((
             token('\t').map(|_| Indent),
-            skip_count_min_max(4, 4, token(' ')).map(|_| Indent),
+            skip_count_min_max(4, 4, token(' ')).message("indent by a tab or 4 spaces").map(|_| Indent),
         ))
     }
 }
@@ -393,8 +480,14 @@ impl std::iter::Extend<Self> for Attribute {
             if let Some(x) = item.joint_head { self.joint_head = Some(x) };
             if let Some(x) = item.joint_tail { self.joint_tail = Some(x) };
             if let Some(x) = item.assign { self.assign = Some(x) };
-            if let Some(x) = item.startable { self.startable = Some(x) };
-            if let Some(x) = item.deadline { self.deadline = Some(x) };
+            if let Some(x) = item.startable {
+                self.startable = Some(x);
+                self.is_hard_startable = item.is_hard_startable;
+            };
+            if let Some(x) = item.deadline {
+                self.deadline = Some(x);
+                self.is_hard_deadline = item.is_hard_deadline;
+            };
             if !item.title.is_empty() {
                 if !self.title.is_empty() {
                     self.title.push(' ');
@@ -419,34 +512,36 @@ parser! {
                 attribute.is_starred = true;
                 attribute
             }),
-            token('#').with(non_nega_i_()).map(|i| {
+            token('#').with(non_nega_i_().expected("id after `#`")).map(|i| {
                 let mut attribute = Attribute::default();
                 attribute.id = Some(i);
                 attribute
             }),
-            token('$').with(non_nega_f_()).map(|f| {
+            token('$').with(non_nega_f_().expected("weight after `$`")).map(|f| {
                 let mut attribute = Attribute::default();
                 attribute.weight = Some(f);
                 attribute
             }),
-            token('@').with(ascii_graphics1_()).map(|ag| {
+            token('@').with(ascii_graphics1_().expected("user name after `@`")).map(|ag| {
                 let mut attribute = Attribute::default();
                 attribute.assign = Some(ag);
                 attribute
             }),
-            token('-').with(datetime_()).map(|dt| {
+            token('-').with(optional(token('!')).and(datetime_().expected("deadline after `-` or `-!`"))).map(|(hard, dt)| {
                 let mut attribute = Attribute::default();
                 attribute.deadline = Some(dt);
+                attribute.is_hard_deadline = hard.is_some();
                 attribute
             }),
-            token('[').with(graphics1_not_joint_()).map(|g| {
+            token('[').with(graphics1_not_joint_().expected("joint name after `[`")).map(|g| {
                 let mut attribute = Attribute::default();
                 attribute.joint_tail = Some(g);
                 attribute
             }),
-            attempt(datetime_().skip(token('-'))).map(|dt| {
+            attempt(datetime_().and(optional(token('!'))).skip(token('-'))).map(|(dt, hard)| {
                 let mut attribute = Attribute::default();
                 attribute.startable = Some(dt);
+                attribute.is_hard_startable = hard.is_some();
                 attribute
             }),
             attempt(graphics1_not_joint_().skip(token(']'))).map(|g| {
@@ -473,6 +568,34 @@ parser! {
         )))
     }
 }
+parser! { // %project for the whole block, or `%` alone to take tasks out of theirs
+    fn project_line_[Input]()(Input) -> String
+    where [ Input: Stream<Token = char> ] {
+        token('%')
+        .with(many(satisfy(|c: char| !c.is_whitespace())))
+        .skip(choice((
+            newline().map(|_| ()),
+            eof(),
+        )))
+    }
+}
+parser! { // link label
+    fn labeled_link_[Input]()(Input) -> ReqLink
+    where [ Input: Stream<Token = char> ] {
+        link_()
+        .and(optional(attempt(inline_spaces1_().with(many::<String, _, _>(satisfy(|c: char| c != '\n'))))))
+        .map(|(url, label)| ReqLink {
+            url: url,
+            label: label.map(|l| l.trim().to_string()).filter(|l| !l.is_empty()),
+        })
+    }
+}
+parser! { // > markdown
+    fn note_line_[Input]()(Input) -> String
+    where [ Input: Stream<Token = char> ] {
+        token('>').with(optional(token(' '))).with(many(satisfy(|c: char| c != '\n')))
+    }
+}
 parser! {
     fn spaces1_[Input]()(Input) -> ()
     where [ Input: Stream<Token = char> ] {
@@ -565,7 +688,6 @@ parser! {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use combine::EasyParser;
 
     fn req_body() -> text::ReqBody {
         text::ReqBody { text: String::from(
@@ -587,13 +709,57 @@ mod tests {
         ));
     }
     #[test]
+    fn t_washer_washed_lines() {
+        let t_00 = req_body().washed_lines();
+        assert_eq!(t_00, vec![
+            (3, String::from("pon")),
+            (15, String::from(" pon")),
+            (17, String::from(" -->")),
+            (19, String::from(" pon")),
+        ]);
+    }
+    #[test]
+    fn t_req_body_parse() {
+        let syntax = |text: &str| match (text::ReqBody { text: String::from(text) }).parse() {
+            Err(errors::ServiceError::BadRequest(body)) => body.syntax,
+            _ => Vec::new(),
+        };
+        let t_00 = syntax("\n/u -a 9:00-8");
+        let t_01 = syntax("#x a\n<!--\n-->\nok\n    b  #y");
+        let t_02 = syntax("a\n  b");
+        let t_10 = syntax("a\n\tb");
+        assert_eq!(t_00, vec![errors::Syntax {
+            line: 2,
+            column: 13,
+            unexpected: Some(String::from("end of input")),
+            expected: vec![String::from("digit"), String::from("`h` after allocation hours")],
+            messages: Vec::new(),
+        }]);
+        assert_eq!(t_00[0].to_string(), "L2:13 unexpected end of input, expected digit or `h` after allocation hours.");
+        assert_eq!(t_01.iter().map(|s| (s.line, s.column)).collect::<Vec<_>>(), vec![(1, 2), (5, 9)]);
+        assert_eq!(t_01[1].unexpected, Some(String::from("`y`")));
+        assert_eq!(t_02.iter().map(|s| (s.line, s.column)).collect::<Vec<_>>(), vec![(2, 3)]);
+        assert!(t_02[0].messages.contains(&String::from("indent by a tab or 4 spaces")));
+        assert!(t_10.is_empty());
+    }
+    #[test]
     fn t_req_() {
         let t_00 = req_().easy_parse("");
         let t_01 = req_().easy_parse("/");
+        let t_02 = req_().easy_parse("%apollo\ntask\n    subtask");
+        let t_03 = req_().easy_parse("%\ntask");
         let t_10 = req_().easy_parse("/12/- * task");
-        assert_eq!(t_00, Ok((Req::Tasks(ReqTasks { tasks: Vec::new() }), "")));
+        let t_11 = req_().easy_parse("%apollo 11\ntask");
+        let project = |r: Result<(Req, &str), _>| match r {
+            Ok((Req::Tasks(ts), "")) => Some((ts.project, ts.tasks.len())),
+            _ => None,
+        };
+        assert_eq!(t_00, Ok((Req::Tasks(ReqTasks { project: None, tasks: Vec::new() }), "")));
         assert_eq!(t_01, Ok((Req::Command(ReqCommand::Help), "")));
+        assert_eq!(project(t_02), Some((Some(String::from("apollo")), 2)));
+        assert_eq!(project(t_03), Some((Some(String::new()), 1)));
         assert_eq!(t_10, Ok((Req::Command(ReqCommand::Help), "12/- * task")));
+        assert_eq!(project(t_11), Some((None, 2))); // a task titled so
     }
     #[test]
     fn t_req_command_() {
@@ -601,12 +767,16 @@ mod tests {
         let t_02 = req_command_().easy_parse("s");
         let t_03 = req_command_().easy_parse("tutorial");
         let t_04 = req_command_().easy_parse("coffee");
+        let t_05 = req_command_().easy_parse("stats");
+        let t_06 = req_command_().easy_parse("s -a");
         let t_10 = req_command_().easy_parse(" ");
         let t_11 = req_command_().easy_parse("x");
         assert_eq!(t_01, Ok((ReqCommand::User(ReqUser::Info), "")));
         assert_eq!(t_02, Ok((ReqCommand::Search(Condition::default()), "")));
         assert_eq!(t_03, Ok((ReqCommand::Tutorial, "")));
         assert_eq!(t_04, Ok((ReqCommand::Coffee, "")));
+        assert_eq!(t_05, Ok((ReqCommand::Stats, "")));
+        assert!(matches!(t_06, Ok((ReqCommand::Search(_), ""))));
         assert!(t_10.is_err());
         assert!(t_11.is_err());
     }
@@ -666,8 +836,9 @@ mod tests {
             r##" 333<#<777 -a!s -l .5<w<24 s<15: /12/<d c 2021//<u<//30T6:"##
         );
         let t_04 = conditions_().easy_parse(
-            r##" 333<#<777 -a!s -l .5<w<24 s<15: /12/<d c 2021//<u<//30T6: "tit le" @r#"double"quoted"man"# &r".*domain\.com.*\?page=[1-5]#(frag|ment)""##
+            r##" 333<#<777 -a!s -l .5<w<24 s<15: /12/<d c 2021//<u<//30T6: "tit le" @r#"double"quoted"man"# &r".*domain\.com.*\?page=[1-5]#(frag|ment)" >"why" %"apollo""##
         );
+        let t_05 = conditions_().easy_parse(" 2021/2/1<a");
         let t_10 = conditions_().easy_parse(" title");
         let t_11 = conditions_().easy_parse(" ");
         assert_eq!(t_00, Ok((
@@ -684,9 +855,12 @@ mod tests {
                 deadline: (None, None),
                 created_at: (None, None),
                 updated_at: (None, None),
+                archived_at: (None, None),
                 title:  None,
                 assign: None,
                 link: None,
+                note: None,
+                project: None,
             },
             ""
         )));
@@ -744,9 +918,12 @@ mod tests {
                         }),
                     })
                 ),
+                archived_at: (None, None),
                 title: None,
                 assign: None,
                 link: None,
+                note: None,
+                project: None,
             },
             ""
         )));
@@ -803,6 +980,7 @@ mod tests {
                         }),
                     })
                 ),
+                archived_at: (None, None),
                 title: Some(text::Expression::Words(vec![
                     String::from("tit"),
                     String::from("le"),
@@ -813,9 +991,26 @@ mod tests {
                 link: Some(text::Expression::Regex(
                     String::from(r".*domain\.com.*\?page=[1-5]#(frag|ment)")
                 )),
+                note: Some(text::Expression::Words(vec![
+                    String::from("why"),
+                ])),
+                project: Some(text::Expression::Words(vec![
+                    String::from("apollo"),
+                ])),
             },
             ""
         )));
+        assert_eq!(t_05.map(|(c, _)| c.archived_at), Ok((
+            Some(models::EasyDateTime {
+                date: Some(models::EasyDate {
+                    y: Some(2021),
+                    m: Some(2),
+                    d: Some(1),
+                }),
+                time: None,
+            }),
+            None
+        )));
         assert!(t_10.is_err());
         assert!(t_11.is_err());
     }
@@ -986,6 +1181,7 @@ mod tests {
         let t_02 = req_task_().easy_parse("\t\ttitle");
         let t_03 = req_task_().easy_parse("    title http://localhost");
         let t_04 = req_task_().easy_parse("    title\n    http://localhost");
+        let t_05 = req_task_().easy_parse("title\nhttp://localhost\n\thttps://example.com/spec  the spec \n    > **why**\n>\n> - because\nnext");
         let t_10 = req_task_().easy_parse("");
         let t_11 = req_task_().easy_parse("      ambiguous indent");
         let t_13 = req_task_().easy_parse("    title\n    some    http://localhost");
@@ -1000,10 +1196,13 @@ mod tests {
                 joint_tail: None,
                 assign: None,
                 startable: None,
+                is_hard_startable: false,
                 deadline: None,
+                is_hard_deadline: false,
                 title: String::from("title"),
             },
-            link: None,
+            links: vec![],
+            note: None,
         }, "")));
         assert_eq!(t_02, Ok((ReqTask {
             indent: 2,
@@ -1015,10 +1214,13 @@ mod tests {
                 joint_tail: None,
                 assign: None,
                 startable: None,
+                is_hard_startable: false,
                 deadline: None,
+                is_hard_deadline: false,
                 title: String::from("title"),
             },
-            link: None,
+            links: vec![],
+            note: None,
         }, "")));
         assert_eq!(t_03, Ok((ReqTask {
             indent: 1,
@@ -1030,10 +1232,13 @@ mod tests {
                 joint_tail: None,
                 assign: None,
                 startable: None,
+                is_hard_startable: false,
                 deadline: None,
+                is_hard_deadline: false,
                 title: String::from("title http://localhost"), // inline links fall into title
             },
-            link: None,
+            links: vec![],
+            note: None,
         }, "")));
         assert_eq!(t_04, Ok((ReqTask {
             indent: 1,
@@ -1045,11 +1250,44 @@ mod tests {
                 joint_tail: None,
                 assign: None,
                 startable: None,
+                is_hard_startable: false,
                 deadline: None,
+                is_hard_deadline: false,
                 title: String::from("title"),
             },
-            link: Some(String::from("http://localhost")), // ok
+            links: vec![ReqLink {
+                url: String::from("http://localhost"),
+                label: None,
+            }], // ok
+            note: None,
         }, "")));
+        assert_eq!(t_05, Ok((ReqTask {
+            indent: 0,
+            attribute: Attribute {
+                is_starred: false,
+                id: None,
+                weight: None,
+                joint_head: None,
+                joint_tail: None,
+                assign: None,
+                startable: None,
+                is_hard_startable: false,
+                deadline: None,
+                is_hard_deadline: false,
+                title: String::from("title"),
+            },
+            links: vec![
+                ReqLink {
+                    url: String::from("http://localhost"),
+                    label: None,
+                },
+                ReqLink {
+                    url: String::from("https://example.com/spec"),
+                    label: Some(String::from("the spec")),
+                },
+            ],
+            note: Some(String::from("**why**\n\n- because")),
+        }, "next")));
         assert!(t_10.is_err());
         assert!(t_11.is_err());
         assert_eq!(t_13, Ok((ReqTask {
@@ -1062,12 +1300,35 @@ mod tests {
                 joint_tail: None,
                 assign: None,
                 startable: None,
+                is_hard_startable: false,
                 deadline: None,
+                is_hard_deadline: false,
                 title: String::from("title"),
             },
-            link: None,
+            links: vec![],
+            note: None,
         }, "    some    http://localhost")));
-        assert!(t_14.is_err());
+        assert_eq!(t_14, Ok((ReqTask {
+            indent: 1,
+            attribute: Attribute {
+                is_starred: false,
+                id: None,
+                weight: None,
+                joint_head: None,
+                joint_tail: None,
+                assign: None,
+                startable: None,
+                is_hard_startable: false,
+                deadline: None,
+                is_hard_deadline: false,
+                title: String::from("title"),
+            },
+            links: vec![ReqLink {
+                url: String::from("http://localhost"),
+                label: Some(String::from("some")), // trailing words label the link
+            }],
+            note: None,
+        }, "")));
     }
     #[test]
     fn t_indents_() {
@@ -1088,6 +1349,10 @@ mod tests {
         let t_02 = attributes1_().easy_parse("#333 h] something * 15:- 魁 -/12/ [t $5 great $530000. @satun ⚡");
         let t_03 = attributes1_().easy_parse("//T: //T //: // T: T :");
         let t_04 = attributes1_().easy_parse("//T- //:- T:- T-");
+        let t_05 = attributes1_().easy_parse("-!2021/3/1 title");
+        let t_06 = attributes1_().easy_parse("-!/3/ -/4/");
+        let t_07 = attributes1_().easy_parse("15:!- title");
+        let t_08 = attributes1_().easy_parse("/3/!- 9:-");
         let t_10 = attributes1_().easy_parse("");
         let t_11 = attributes1_().easy_parse(" ");
         let t_12 = attributes1_().easy_parse("\n");
@@ -1101,6 +1366,7 @@ mod tests {
         let t_20 = attributes1_().easy_parse("$");
         let t_21 = attributes1_().easy_parse("@");
         let t_22 = attributes1_().easy_parse("-T: -T");
+        let t_23 = attributes1_().easy_parse("-! title");
         let mut attr = Attribute::default();
         assert_eq!(t_00, Ok(({ attr.title = String::from("https://"); attr }, "")));
         assert_eq!(t_02, Ok((Attribute {
@@ -1117,6 +1383,7 @@ mod tests {
                     m: None,
                 }),
             }),
+            is_hard_startable: false,
             deadline: Some(models::EasyDateTime {
                 date: Some(models::EasyDate {
                     y: None,
@@ -1125,12 +1392,19 @@ mod tests {
                 }),
                 time: None,
             }),
+            is_hard_deadline: false,
             title: String::from("something 魁 great ⚡"),
         }, "")));
         let mut attr = Attribute::default();
         assert_eq!(t_03, Ok(({ attr.title = String::from("//T: //T //: // T: T :"); attr }, "")));
         let mut attr = Attribute::default();
         assert_eq!(t_04, Ok(({ attr.title = String::from("//T- //:- T:- T-"); attr }, "")));
+        let hard = |r: Result<(Attribute, &str), _>| r.map(|(a, _)| (a.deadline.and_then(|dt| dt.date).and_then(|d| d.m), a.is_hard_deadline));
+        assert_eq!(hard(t_05), Ok((Some(3), true)));
+        assert_eq!(hard(t_06), Ok((Some(4), false))); // the last one counts
+        let hard = |r: Result<(Attribute, &str), _>| r.map(|(a, _)| (a.startable.and_then(|dt| dt.time).and_then(|t| t.h), a.is_hard_startable));
+        assert_eq!(hard(t_07), Ok((Some(15), true)));
+        assert_eq!(hard(t_08), Ok((Some(9), false)));
         assert!(t_10.is_err());
         assert!(t_11.is_err());
         assert!(t_12.is_err());
@@ -1149,6 +1423,7 @@ mod tests {
         assert!(t_20.is_err());
         assert!(t_21.is_err());
         assert!(t_22.is_err());
+        assert!(t_23.is_err());
     }
     #[test]
     fn t_link_() {
@@ -1162,6 +1437,26 @@ mod tests {
         assert!(t_11.is_err());
     }
     #[test]
+    fn t_labeled_link_() {
+        let t_00 = labeled_link_().easy_parse("https://example.com/a");
+        let t_01 = labeled_link_().easy_parse("https://example.com/a   design  doc \nnext");
+        let t_02 = labeled_link_().easy_parse("https://example.com/a   ");
+        let t_10 = labeled_link_().easy_parse("design doc https://example.com/a");
+        assert_eq!(t_00, Ok((ReqLink {
+            url: String::from("https://example.com/a"),
+            label: None,
+        }, "")));
+        assert_eq!(t_01, Ok((ReqLink {
+            url: String::from("https://example.com/a"),
+            label: Some(String::from("design  doc")),
+        }, "\nnext")));
+        assert_eq!(t_02, Ok((ReqLink {
+            url: String::from("https://example.com/a"),
+            label: None,
+        }, "")));
+        assert!(t_10.is_err());
+    }
+    #[test]
     fn t_inline_spaces1_() {
         let t_00 = inline_spaces1_().easy_parse(" ");
         let t_01 = inline_spaces1_().easy_parse("   \n   ");
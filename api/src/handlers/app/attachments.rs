@@ -0,0 +1,304 @@
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Serialize, Deserialize};
+use std::path::PathBuf;
+
+use crate::broadcast;
+use crate::errors;
+use crate::models;
+use crate::schema::attachments;
+use crate::webhook;
+use super::comments::visible;
+
+// what may be uploaded, each checked against the content as well
+const TYPES: [&str; 9] = [
+    "application/pdf",
+    "application/zip",
+    "image/gif",
+    "image/jpeg",
+    "image/png",
+    "image/webp",
+    "text/csv",
+    "text/markdown",
+    "text/plain",
+];
+
+#[derive(Deserialize)]
+pub struct Q {
+    name: String,
+}
+
+#[derive(Serialize)]
+pub struct ResAttachment {
+    id: i32,
+    name: String,
+    mime: String,
+    size: i32,
+    created_at: DateTime<Utc>,
+}
+
+// the raw body as the file, its type in Content-Type and its name in ?name=
+pub async fn upload(
+    tid: web::Path<i32>,
+    q: web::Query<Q>,
+    http_req: HttpRequest,
+    body: web::Bytes,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+    broadcaster: web::Data<broadcast::Broadcaster>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_write()?;
+    let declared = http_req.headers().get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_lowercase())
+        .unwrap_or_default();
+    let res_body = web::block(move || {
+        use crate::schema::attachments::dsl::attachments;
+
+        let conn = pool.get().unwrap();
+        let tid = tid.into_inner();
+        editable(tid, &user, &conn)?;
+        if max_bytes() < body.len() {
+            return Err(errors::ServiceError::bad_request(errors::Code::TooLarge, format!(
+                "attachment exceeds {} bytes.",
+                max_bytes(),
+            )).task_id(tid))
+        }
+        if !accepts(&declared, &body) {
+            return Err(errors::ServiceError::bad_request(errors::Code::UnsupportedType, format!(
+                "{}: type not accepted, or not matching the content.",
+                declared,
+            )).task_id(tid).field("mime"))
+        }
+        let attachment = conn.transaction::<_, errors::ServiceError, _>(|| {
+            let attachment = diesel::insert_into(attachments).values(&NewAttachment {
+                task: tid,
+                uploader: user.id,
+                name: sanitize(&q.name),
+                mime: declared,
+                size: body.len() as i32,
+            }).get_result::<models::Attachment>(&conn)?;
+            // the row goes back if the file does not make it to the disk
+            std::fs::create_dir_all(dir())
+                .and_then(|_| std::fs::write(path(attachment.id), &body))
+                .map_err(|err| {
+                    println!("Attachment Error:\n{:#?}", err);
+                    errors::ServiceError::InternalServerError
+                })?;
+            Ok(attachment)
+        })?;
+        webhook::emit(webhook::Event::TaskUpdated, &user, &vec![tid], &Vec::new(), &conn)?;
+        broadcaster.send(&broadcast::Message::new(webhook::Event::TaskUpdated, &vec![tid], &Vec::new(), &conn)?);
+
+        Ok(ResAttachment::from(attachment))
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+pub async fn download(
+    aid: web::Path<i32>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    let (attachment, bytes) = web::block(move || {
+        let conn = pool.get().unwrap();
+        let attachment = find(aid.into_inner(), &conn)?;
+        visible(attachment.task, &user, &conn)?;
+        let bytes = std::fs::read(path(attachment.id)).map_err(|err| {
+            println!("Attachment Error:\n{:#?}", err);
+            errors::ServiceError::InternalServerError
+        })?;
+        Ok((attachment, bytes))
+    }).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(attachment.mime.as_str())
+        .header(header::CONTENT_DISPOSITION, disposition(&attachment.name))
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .body(bytes))
+}
+
+pub async fn delete(
+    aid: web::Path<i32>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+    broadcaster: web::Data<broadcast::Broadcaster>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_write()?;
+    let _ = web::block(move || {
+        use crate::schema::attachments::dsl::attachments;
+
+        let conn = pool.get().unwrap();
+        let attachment = find(aid.into_inner(), &conn)?;
+        editable(attachment.task, &user, &conn)?;
+        diesel::delete(attachments.find(attachment.id)).execute(&conn)?;
+        // a file left behind is only a waste of space
+        if let Err(err) = std::fs::remove_file(path(attachment.id)) {
+            println!("Attachment Error:\n{:#?}", err);
+        }
+        webhook::emit(webhook::Event::TaskUpdated, &user, &vec![attachment.task], &Vec::new(), &conn)?;
+        broadcaster.send(&broadcast::Message::new(webhook::Event::TaskUpdated, &vec![attachment.task], &Vec::new(), &conn)?);
+        Ok(())
+    }).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub fn max_bytes() -> usize {
+    std::env::var("ATTACHMENT_MAX_BYTES").ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(10 * 1024 * 1024)
+}
+
+fn dir() -> PathBuf {
+    PathBuf::from(std::env::var("ATTACHMENT_DIR").unwrap_or_else(|_| String::from("attachments")))
+}
+
+// by id, not to trust the uploaded name with the file system
+fn path(aid: i32) -> PathBuf {
+    dir().join(aid.to_string())
+}
+
+fn find(aid: i32, conn: &models::Conn) -> Result<models::Attachment, errors::ServiceError> {
+    use crate::schema::attachments::dsl::attachments;
+
+    attachments.find(aid).first::<models::Attachment>(conn).optional()?.ok_or_else(|| {
+        errors::ServiceError::bad_request(errors::Code::NotFound, format!(
+            "attachment {}: not found.",
+            aid,
+        ))
+    })
+}
+
+// the same permission as to edit the task by text
+pub(super) fn editable(
+    tid: i32,
+    user: &models::AuthedUser,
+    conn: &models::Conn,
+) -> Result<(), errors::ServiceError> {
+    use diesel::dsl::{select, exists};
+    use crate::schema::tasks::dsl::{tasks, id};
+
+    if select(exists(tasks
+        .filter(id.eq(&tid))
+        .filter(user.permits(true))
+    )).get_result(conn)? {
+        return Ok(())
+    }
+    Err(errors::ServiceError::bad_request(errors::Code::NotFound, format!(
+        "#{}: item not found, or no edit permission.",
+        tid,
+    )).task_id(tid))
+}
+
+fn accepts(declared: &str, bytes: &[u8]) -> bool {
+    TYPES.contains(&declared) && match sniff(bytes) {
+        Some("text/plain") => declared.starts_with("text/"),
+        Some(sniffed) => sniffed == declared,
+        None => false,
+    }
+}
+
+// by magic numbers, and anything else in UTF-8 as text
+fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else if bytes.starts_with(b"PK\x03\x04") {
+        Some("application/zip")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.starts_with(b"\xFF\xD8\xFF") {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1A\n") {
+        Some("image/png")
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        Some("image/webp")
+    } else if std::str::from_utf8(bytes).map_or(false, |s| !s.contains('\0')) {
+        Some("text/plain")
+    } else {
+        None
+    }
+}
+
+// a base name without controls or quotes, to echo back in headers
+fn sanitize(name: &str) -> String {
+    let name = name.rsplit(|c| c == '/' || c == '\\').next().unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(255)
+        .collect::<String>();
+    match name.trim() {
+        "" | "." | ".." => String::from("attachment"),
+        name => name.to_string(),
+    }
+}
+
+// ASCII fallback for old clients, and the UTF-8 name for the others
+fn disposition(name: &str) -> String {
+    let fallback = name.chars().map(|c| if c.is_ascii() { c } else { '_' }).collect::<String>();
+    let encoded = name.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect::<String>();
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", fallback, encoded)
+}
+
+#[derive(Insertable)]
+#[table_name = "attachments"]
+struct NewAttachment {
+    task: i32,
+    uploader: i32,
+    name: String,
+    mime: String,
+    size: i32,
+}
+
+impl From<models::Attachment> for ResAttachment {
+    fn from(attachment: models::Attachment) -> Self {
+        Self {
+            id: attachment.id,
+            name: attachment.name,
+            mime: attachment.mime,
+            size: attachment.size,
+            created_at: attachment.created_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_accepts() {
+        assert!(accepts("image/png", b"\x89PNG\r\n\x1A\n...."));
+        assert!(accepts("application/pdf", b"%PDF-1.4 ..."));
+        assert!(accepts("text/markdown", "# メモ\n- item".as_bytes()));
+        assert!(accepts("text/plain", b""));
+        assert!(!accepts("image/png", b"%PDF-1.4 ...")); // disguised
+        assert!(!accepts("text/plain", b"\xFF\xD8\xFF\xE0"));
+        assert!(!accepts("text/html", b"<script></script>")); // not in the list
+        assert!(!accepts("application/octet-stream", b"\x00\x01\x02"));
+    }
+    #[test]
+    fn t_sanitize() {
+        assert_eq!(sanitize("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize("C:\\Users\\me\\report.pdf"), "report.pdf");
+        assert_eq!(sanitize("say \"hi\"\r\n.txt"), "say hi.txt");
+        assert_eq!(sanitize(".."), "attachment");
+        assert_eq!(sanitize("dir/"), "attachment");
+    }
+    #[test]
+    fn t_disposition() {
+        assert_eq!(
+            disposition("見積 v2.pdf"),
+            "attachment; filename=\"__ v2.pdf\"; filename*=UTF-8''%E8%A6%8B%E7%A9%8D%20v2.pdf",
+        );
+    }
+}
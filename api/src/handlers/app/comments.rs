@@ -0,0 +1,134 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::broadcast;
+use crate::cache;
+use crate::errors;
+use crate::models;
+use crate::schema::comments;
+use crate::webhook;
+
+#[derive(Deserialize)]
+pub struct ReqBody {
+    body: String,
+}
+
+#[derive(Serialize)]
+struct ResBody {
+    comments: Vec<ResComment>,
+}
+
+#[derive(Serialize)]
+struct ResComment {
+    id: i32,
+    author: String,
+    body: String,
+    created_at: DateTime<Utc>,
+}
+
+pub async fn list(
+    tid: web::Path<i32>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    let res_body = web::block(move || {
+        use crate::schema::comments::dsl::{comments, task, created_at};
+        use crate::schema::users::dsl::users;
+
+        let conn = pool.get().unwrap();
+        let tid = tid.into_inner();
+        visible(tid, &user, &conn)?;
+        let _comments = comments
+            .filter(task.eq(&tid))
+            .inner_join(users)
+            .order(created_at)
+            .load::<(models::Comment, models::User)>(&conn)?
+            .into_iter().map(|c| c.into()).collect::<Vec<ResComment>>();
+
+        Ok(ResBody {
+            comments: _comments,
+        })
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+pub async fn post(
+    tid: web::Path<i32>,
+    req: web::Json<ReqBody>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+    cache: web::Data<cache::Cache>,
+    broadcaster: web::Data<broadcast::Broadcaster>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_write()?;
+    let res_body = web::block(move || {
+        use crate::schema::comments::dsl::comments;
+        use crate::schema::users::dsl::users;
+
+        let conn = pool.get().unwrap();
+        let tid = tid.into_inner();
+        visible(tid, &user, &conn)?;
+        let body = req.into_inner().body.trim().to_string();
+        if body.is_empty() {
+            return Err(errors::ServiceError::bad_request(errors::Code::EmptyComment, "comment is empty.").field("body"))
+        }
+        let comment = diesel::insert_into(comments).values(&NewComment {
+            task: tid,
+            author: user.id,
+            body: body,
+        }).get_result::<models::Comment>(&conn)?;
+        let author = users.find(user.id).first::<models::User>(&conn)?;
+        webhook::emit(webhook::Event::CommentCreated, &user, &vec![tid], &Vec::new(), &conn)?;
+        broadcaster.send(&broadcast::Message::new(webhook::Event::CommentCreated, &vec![tid], &Vec::new(), &conn)?);
+
+        Ok(ResComment::from((comment, author)))
+    }).await?;
+
+    cache.invalidate(); // for the comment counts
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+// the same permission as to focus on the task
+pub(super) fn visible(
+    tid: i32,
+    user: &models::AuthedUser,
+    conn: &models::Conn,
+) -> Result<(), errors::ServiceError> {
+    use diesel::dsl::{select, exists};
+    use crate::schema::tasks::dsl::{tasks, id};
+
+    if select(exists(tasks
+        .filter(id.eq(&tid))
+        .filter(user.permits(false))
+    )).get_result(conn)? {
+        return Ok(())
+    }
+    Err(errors::ServiceError::bad_request(errors::Code::NotFound, format!(
+        "#{}: item not found.",
+        tid,
+    )).task_id(tid))
+}
+
+#[derive(Insertable)]
+#[table_name = "comments"]
+struct NewComment {
+    task: i32,
+    author: i32,
+    body: String,
+}
+
+impl From<(models::Comment, models::User)> for ResComment {
+    fn from((comment, author): (models::Comment, models::User)) -> Self {
+        Self {
+            id: comment.id,
+            author: author.name,
+            body: comment.body,
+            created_at: comment.created_at,
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use actix_web::{http::header, web, HttpResponse};
+use futures::StreamExt;
+
+use crate::broadcast;
+use crate::errors;
+use crate::models;
+
+// text/event-stream of task and arrow changes visible to the user, until the client goes away
+// the web client does not listen yet: it is served as elm make generates it, with no page to host a port
+pub async fn events(
+    user: models::AuthedUser,
+    broadcaster: web::Data<broadcast::Broadcaster>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    let receiver = broadcaster.subscribe(user.id);
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .streaming(receiver.map(Ok::<_, actix_web::Error>)))
+}
@@ -1,9 +1,14 @@
 use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
 use serde::{Serialize, Deserialize};
 
+use crate::broadcast;
+use crate::cache;
 use crate::errors;
 use crate::models;
+use crate::schema::completions;
+use crate::webhook;
 
 #[derive(Deserialize)]
 pub struct ReqBody {
@@ -21,67 +26,90 @@ pub async fn exec(
     req: web::Json<ReqBody>,
     user: models::AuthedUser,
     pool: web::Data<models::Pool>,
+    cache: web::Data<cache::Cache>,
+    broadcaster: web::Data<broadcast::Broadcaster>,
 ) -> Result<HttpResponse, errors::ServiceError> {
 
+    user.require_write()?;
     let res_body = web::block(move || {
-        use diesel::dsl::exists;
-        use crate::schema::arrows::dsl::arrows;
-        use crate::schema::permissions::dsl::*;
-        use crate::schema::tasks::dsl::{tasks, id, assign, is_archived};
+        use crate::schema::completions::dsl::{completions, task, reverted_by, reverted_at};
+        use crate::schema::tasks::dsl::{tasks, id, is_archived, archived_at, archived_by};
 
         let conn = pool.get().unwrap();
         let req = req.into_inner();
-        let _arrows: models::Arrows = arrows.load::<models::Arrow>(&conn)?.into();
         let entries = req.verify(&user, &conn)?;
-        let targets = entries.iter()
-        .flat_map(|tid| models::Tid::from(*tid).nodes_to(
+        let targets = user.nodes_to(
             if req.revert { models::LR::Root } else { models::LR::Leaf }
-            , &_arrows)
-        ).collect::<Vec<i32>>();
+            , &entries, &conn)?;
 
-        let count = diesel::update(tasks
-            .filter(exists(permissions
-                .filter(subject.eq(&user.id))
-                .filter(object.eq(assign))
-                .filter(edit)
-            ))
-            .filter(is_archived.eq(&req.revert))
-            .filter(id.eq_any(&targets))
-        ).set(is_archived.eq(&!req.revert)).execute(&conn)?;
+        let now = Utc::now();
+        let changed = conn.transaction::<_, errors::ServiceError, _>(|| {
+            let changed = diesel::update(tasks
+                .filter(user.permits(true))
+                .filter(is_archived.eq(&req.revert))
+                .filter(id.eq_any(&targets))
+            ).set((
+                is_archived.eq(&!req.revert),
+                archived_at.eq(if req.revert { None } else { Some(now) }),
+                archived_by.eq(if req.revert { None } else { Some(user.id) }),
+            )).returning(id).get_results::<i32>(&conn)?;
+            // the history outlives reverts
+            if req.revert {
+                diesel::update(completions
+                    .filter(task.eq_any(&changed))
+                    .filter(reverted_at.is_null())
+                ).set((
+                    reverted_by.eq(&user.id),
+                    reverted_at.eq(&now),
+                )).execute(&conn)?;
+            } else {
+                diesel::insert_into(completions).values(&changed.iter().map(|tid| NewCompletion {
+                    task: *tid,
+                    archived_by: user.id,
+                    archived_at: now,
+                }).collect::<Vec<NewCompletion>>()).execute(&conn)?;
+            }
+            Ok(changed)
+        })?;
+        let event = if req.revert { webhook::Event::TaskReverted } else { webhook::Event::TaskArchived };
+        webhook::emit(event, &user, &changed, &Vec::new(), &conn)?;
+        broadcaster.send(&broadcast::Message::new(event, &changed, &Vec::new(), &conn)?);
 
         Ok(ResBody {
-            count: count,
-            chain: count - entries.len(),
+            count: changed.len(),
+            chain: changed.len() - entries.len(),
         })
     }).await?;
 
+    cache.invalidate();
     Ok(HttpResponse::Ok().json(res_body))
 }
 
+#[derive(Insertable)]
+#[table_name = "completions"]
+struct NewCompletion {
+    task: i32,
+    archived_by: i32,
+    archived_at: DateTime<Utc>,
+}
+
 impl ReqBody {
     fn verify(&self,
         user: &models::AuthedUser,
         conn: &models::Conn,
     ) -> Result<Vec<i32>, errors::ServiceError> {
-        use diesel::dsl::exists;
-        use crate::schema::permissions::dsl::*;
-        use crate::schema::tasks::dsl::{tasks, id, assign, is_archived};
+        use diesel::dsl::not;
+        use crate::schema::tasks::dsl::{tasks, id, is_archived};
 
         if let Some(tid) = tasks
         .filter(id.eq_any(&self.tasks))
-        .filter(
-            exists(permissions
-                .filter(subject.eq(&user.id))
-                .filter(object.eq(assign))
-                .filter(edit)
-            ).eq(false)
-        )
+        .filter(not(user.permits(true)))
         .select(id)
         .first::<i32>(conn).ok() {
-            return Err(errors::ServiceError::BadRequest(format!(
+            return Err(errors::ServiceError::bad_request(errors::Code::NoEditPermission, format!(
                 "#{}: no edit permission.",
                 tid
-            )))
+            )).task_id(tid))
         }
         Ok(tasks
             .filter(is_archived.eq(&self.revert))
@@ -1,18 +1,56 @@
 use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
-use serde::Serialize;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
 
 use crate::errors;
+use crate::graph;
 use crate::models::{self, Selectable};
+use super::attachments::ResAttachment;
+use super::links::ResLink;
+
+#[derive(Deserialize)]
+pub struct Q {
+    // steps to ancestors and descendants, or all the way with `full`
+    depth: Option<usize>,
+    #[serde(default)]
+    full: bool,
+}
 
 #[derive(Serialize)]
 pub struct ResBody {
     pred: Vec<models::ResTask>,
     succ: Vec<models::ResTask>,
+    note: Option<String>,
+    links: Vec<ResLink>,
+    attachments: Vec<ResAttachment>,
+    completions: Vec<ResCompletion>, // archived and reverted so far
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ancestors: Option<Vec<ResRelated>>, // toward roots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    descendants: Option<Vec<ResRelated>>, // toward leaves
+}
+
+#[derive(Serialize)]
+struct ResRelated {
+    #[serde(flatten)]
+    task: models::ResTask,
+    distance: usize,
+    path: Vec<i32>, // from the focused task to this one
+}
+
+#[derive(Serialize)]
+struct ResCompletion {
+    archived_by: Option<String>,
+    archived_at: DateTime<Utc>,
+    reverted_by: Option<String>,
+    reverted_at: Option<DateTime<Utc>>,
 }
 
 pub async fn focus(
     tid: web::Path<i32>,
+    q: web::Query<Q>,
     user: models::AuthedUser,
     pool: web::Data<models::Pool>,
 ) -> Result<HttpResponse, errors::ServiceError> {
@@ -20,33 +58,100 @@ pub async fn focus(
     let res_body = web::block(move || {
         use diesel::dsl::exists;
         use crate::schema::arrows::dsl::*;
-        use crate::schema::permissions::dsl::*;
-        use crate::schema::tasks::dsl::{tasks, id, assign};
+        use crate::schema::tasks::dsl::{tasks, id, note};
         use crate::schema::users::dsl::users;
 
         let conn = pool.get().unwrap();
         let tid = tid.into_inner();
         let query = tasks
-        .filter(exists(permissions
-            .filter(subject.eq(&user.id))
-            .filter(object.eq(assign))
-        ))
+        .filter(user.permits(false))
         .inner_join(users)
         .select(models::SelTask::columns());
 
-        let pred = query
+        let pred = query.clone()
         .filter(exists(arrows.filter(source.eq(id)).filter(target.eq(&tid))))
         .load::<models::SelTask>(&conn)?
         .into_iter().map(|t| t.to_res()).collect();
 
-        let succ = query
+        let succ = query.clone()
         .filter(exists(arrows.filter(source.eq(&tid)).filter(target.eq(id))))
         .load::<models::SelTask>(&conn)?
         .into_iter().map(|t| t.to_res()).collect();
 
+        // the focused task itself, under the same permission
+        let _note = tasks
+        .filter(id.eq(&tid))
+        .filter(user.permits(false))
+        .select(note)
+        .first::<Option<String>>(&conn).optional()?;
+        let (_links, _attachments, _completions) = match _note {
+            None => (Vec::new(), Vec::new(), Vec::new()),
+            Some(_) => {
+                use crate::schema::attachments::dsl as a;
+                use crate::schema::completions::dsl as c;
+                use crate::schema::links::dsl as l;
+                use crate::schema::users::dsl as u;
+
+                let completions = c::completions.filter(c::task.eq(&tid)).order(c::id)
+                    .load::<models::Completion>(&conn)?;
+                let names = u::users
+                    .filter(u::id.eq_any(completions.iter()
+                        .flat_map(|c| vec![c.archived_by, c.reverted_by])
+                        .flatten()
+                        .collect::<Vec<i32>>()))
+                    .select((u::id, u::name))
+                    .load::<(i32, String)>(&conn)?
+                    .into_iter().collect::<HashMap<i32, String>>();
+                (
+                    l::links.filter(l::task.eq(&tid)).order(l::id)
+                    .load::<models::Link>(&conn)?
+                    .into_iter().map(|l| l.into()).collect(),
+                    a::attachments.filter(a::task.eq(&tid)).order(a::id)
+                    .load::<models::Attachment>(&conn)?
+                    .into_iter().map(|a| a.into()).collect(),
+                    completions.into_iter().map(|c| ResCompletion {
+                        archived_by: c.archived_by.and_then(|u| names.get(&u).cloned()),
+                        archived_at: c.archived_at,
+                        reverted_by: c.reverted_by.and_then(|u| names.get(&u).cloned()),
+                        reverted_at: c.reverted_at,
+                    }).collect(),
+                )
+            },
+        };
+
+        let (mut ancestors, mut descendants) = (None, None);
+        if q.full || q.depth.is_some() {
+            let depth = if q.full { None } else { q.depth };
+            for (lr, related) in vec![
+                (models::LR::Root, &mut ancestors),
+                (models::LR::Leaf, &mut descendants),
+            ] {
+                // permitted ones only, then the shortest paths among them
+                let ids = user.nodes_to(lr, &vec![tid], &conn)?;
+                let mut reachables = query.clone()
+                .filter(id.eq_any(&ids))
+                .load::<models::SelTask>(&conn)?
+                .into_iter().map(|t| (t.id, t.to_res())).collect::<HashMap<i32, models::ResTask>>();
+                let _arrows = models::Arrows::among(&reachables.values().cloned().collect(), &conn)?;
+                *related = Some(graph::Graph::from(&_arrows).paths_from(lr, tid, depth).into_iter()
+                .filter_map(|(node, path)| reachables.remove(&node).map(|t| ResRelated {
+                    task: t,
+                    distance: path.len() - 1,
+                    path: path,
+                }))
+                .collect());
+            }
+        }
+
         Ok(ResBody {
             pred: pred,
             succ: succ,
+            note: _note.flatten(),
+            links: _links,
+            attachments: _attachments,
+            completions: _completions,
+            ancestors: ancestors,
+            descendants: descendants,
         })
     }).await?;
 
@@ -7,14 +7,17 @@ use interval::interval_set::ToIntervalSet;
 use interval::interval_set::{IntervalSet};
 use serde::{Serialize, Deserialize};
 use std::cmp::{max, min};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
+use crate::cache;
 use crate::errors;
+use crate::graph;
 use crate::models::{self, Selectable};
 
 #[derive(Deserialize, Serialize)]
 pub struct Q {
     pub option: Option<String>,
+    pub project: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -26,11 +29,15 @@ pub async fn home(
     q: web::Query<Q>,
     user: models::AuthedUser,
     pool: web::Data<models::Pool>,
+    cache: web::Data<cache::Cache>,
 ) -> Result<HttpResponse, errors::ServiceError> {
 
     let res_body = web::block(move || {
         let conn = pool.get().unwrap();
-        let res_tasks = q.into_inner().config().query(&user, &conn)?;
+        let res_tasks = match &q.project {
+            Some(project) => q.config().query_project(project, &user, &conn)?,
+            None => q.config().query(&user, &conn, &cache)?,
+        };
 
         Ok(ResBody {
             tasks: res_tasks,
@@ -63,9 +70,9 @@ impl Config {
     pub fn query(&self,
         user: &models::AuthedUser,
         conn: &models::Conn,
+        cache: &cache::Cache,
     ) -> Result<Vec<models::ResTask>, errors::ServiceError> {
-        use crate::schema::allocations::dsl::{allocations, owner};
-        use crate::schema::tasks::dsl::{tasks, assign, is_archived, is_starred, updated_at};
+        use crate::schema::tasks::dsl::{tasks, assign, is_archived, is_starred, updated_at, archived_at};
         use crate::schema::users::dsl::users;
 
         let is_archives = *self == Self::Archives;
@@ -77,28 +84,91 @@ impl Config {
         if is_archives {
             return Ok(
                 _intermediate
-                .order((is_starred.desc(), updated_at.desc()))
+                .order((is_starred.desc(), archived_at.desc(), updated_at.desc()))
                 .limit(100)
                 .load::<models::SelTask>(conn)?
                 .into_iter().map(|t| t.to_res()).collect()
             )
         }
-        let mut res_tasks = _intermediate
-            .order(updated_at.desc())
-            .load::<models::SelTask>(conn)?
-            .into_iter().map(|t| t.to_res()).collect();
-        let arrows = models::Arrows::among(&res_tasks, conn)?;
-        let _allocations = allocations
-            .filter(owner.eq(&user.id))
-            .select(models::Allocation::columns())
-            .load::<models::Allocation>(conn)?;
-        let sorter = Sorter {
-            allocations: _allocations,
-            now: Utc::now(),
-            tz: user.tz,
+        let mut res_tasks = match cache.get(user) {
+            Some(res_tasks) => res_tasks,
+            None => {
+                let generation = cache.generation();
+                let mut res_tasks = _intermediate
+                    .order(updated_at.desc())
+                    .load::<models::SelTask>(conn)?
+                    .into_iter().map(|t| t.to_res()).collect();
+                let arrows = models::Arrows::among(&res_tasks, conn)?;
+                sort(&mut res_tasks, arrows, user, conn)?;
+                cache.set(user, &res_tasks, generation);
+                res_tasks
+            },
         };
-        sorter.exec(&mut res_tasks, arrows.clone());
-        self.filter(&mut res_tasks, &arrows);
+        if *self != Self::Home {
+            let arrows = models::Arrows::among(&res_tasks, conn)?;
+            self.filter(&mut res_tasks, &arrows);
+        }
+        Ok(res_tasks)
+    }
+    // every permitted task of a project the user is in, each assignee's scheduled on their own allocations
+    // and listed together, the user's first
+    pub fn query_project(&self,
+        project: &str,
+        user: &models::AuthedUser,
+        conn: &models::Conn,
+    ) -> Result<Vec<models::ResTask>, errors::ServiceError> {
+        use diesel::dsl::exists;
+        use crate::schema::project_members::dsl::{project_members, member, project as of_project};
+        use crate::schema::projects::dsl::{projects, id as pid, name};
+        use crate::schema::tasks::dsl::{tasks, assign, is_archived, is_starred, updated_at, archived_at, project as in_project};
+        use crate::schema::users::dsl::users;
+
+        let project_id = match projects
+            .filter(name.eq(project))
+            .filter(exists(project_members
+                .filter(of_project.eq(pid))
+                .filter(member.eq(&user.id))
+            ))
+            .select(pid)
+            .first::<i32>(conn).optional()? {
+                Some(project_id) => project_id,
+                None => return Ok(Vec::new()),
+            };
+        let is_archives = *self == Self::Archives;
+        let _intermediate = tasks
+            .filter(in_project.eq(&project_id))
+            .filter(user.permits(false))
+            .filter(is_archived.eq(&is_archives))
+            .inner_join(users);
+        if is_archives {
+            return Ok(
+                _intermediate
+                .select(models::SelTask::columns())
+                .order((is_starred.desc(), archived_at.desc(), updated_at.desc()))
+                .limit(100)
+                .load::<models::SelTask>(conn)?
+                .into_iter().map(|t| t.to_res()).collect()
+            )
+        }
+        let mut groups = BTreeMap::<(bool, String), (i32, Vec<models::ResTask>)>::new();
+        for (t, a) in _intermediate
+            .select((models::SelTask::columns(), assign))
+            .order(updated_at.desc())
+            .load::<(models::SelTask, i32)>(conn)? {
+                let t = t.to_res();
+                groups.entry((a != user.id, t.assign.clone())).or_insert((a, Vec::new())).1.push(t);
+            }
+        let mut res_tasks = Vec::new();
+        for (_, (a, mut group)) in groups {
+            let arrows = models::Arrows::among(&group, conn)?;
+            let tz = if a == user.id { user.tz } else { last_tz(a, conn)?.unwrap_or(user.tz) };
+            Sorter::of(a, tz, conn)?.exec(&mut group, arrows);
+            res_tasks.extend(group);
+        }
+        if *self != Self::Home {
+            let arrows = models::Arrows::among(&res_tasks, conn)?;
+            self.filter(&mut res_tasks, &arrows);
+        }
         Ok(res_tasks)
     }
     fn filter(&self, tasks: &mut Vec<models::ResTask>, arrows: &models::Arrows) {
@@ -114,20 +184,74 @@ impl Config {
     }
 }
 
-struct Sorter {
+// allocations of others are in their local time, as of their last session
+fn last_tz(user_id: i32, conn: &models::Conn) -> Result<Option<Tz>, errors::ServiceError> {
+    use crate::schema::sessions::dsl::{sessions, owner, last_seen_at, tz};
+
+    Ok(sessions
+        .filter(owner.eq(&user_id))
+        .order(last_seen_at.desc())
+        .select(tz)
+        .first::<String>(conn).optional()?
+        .and_then(|s| s.parse::<Tz>().ok()))
+}
+
+// schedule, prioritize and order tasks on the user's allocations
+pub fn sort(
+    tasks: &mut Vec<models::ResTask>,
+    arrows: models::Arrows,
+    user: &models::AuthedUser,
+    conn: &models::Conn,
+) -> Result<(), errors::ServiceError> {
+    Sorter::new(user, conn)?.exec(tasks, arrows);
+    Ok(())
+}
+
+pub(super) struct Sorter {
     allocations: Vec<models::Allocation>,
     now: DateTime<Utc>,
     tz: Tz,
 }
 
 impl Sorter {
+    pub(super) fn new(
+        user: &models::AuthedUser,
+        conn: &models::Conn,
+    ) -> Result<Self, errors::ServiceError> {
+        Self::of(user.id, user.tz, conn)
+    }
+    fn of(
+        user_id: i32,
+        tz: Tz,
+        conn: &models::Conn,
+    ) -> Result<Self, errors::ServiceError> {
+        use crate::schema::allocations::dsl::{allocations, owner};
+
+        let _allocations = allocations
+            .filter(owner.eq(&user_id))
+            .select(models::Allocation::columns())
+            .load::<models::Allocation>(conn)?;
+        Ok(Self {
+            allocations: _allocations,
+            now: Utc::now(),
+            tz: tz,
+        })
+    }
+    // hours allocated between the two, none without allocations
+    pub(super) fn hours_between(&self, l: DateTime<Utc>, r: DateTime<Utc>) -> Option<f32> {
+        if self.daily() == 0 { return None }
+        Some((self.splice(r) - self.splice(l)) as f32 / 3600.0)
+    }
     fn exec(&self, tasks: &mut Vec<models::ResTask>, arrows: models::Arrows) {
         let mut sub = self.to_sub(tasks, arrows);
         sub.exec();
-        // set priority
+        // set priority, in hours from seconds
+        let hours = |p: Option<i64>| p.map(|p| p as f32 / 3600.0);
         for t in tasks.iter_mut() {
-            if let Some(p) = sub.map[&t.id].priority {
-                t.priority = Some(p as f32 / 3600.0) // hours from seconds
+            t.hard_priority = hours(sub.map[&t.id].hard_priority);
+            t.soft_priority = hours(sub.map[&t.id].soft_priority);
+            if let Some(p) = t.hard_priority.or(t.soft_priority) {
+                t.priority = Some(p)
             }
         }
         if 0 < self.daily() {
@@ -149,8 +273,11 @@ impl Sorter {
         for t in tasks {
             map.insert(t.id, SubTask {
                 startable: t.startable.map(|dt| self.splice(dt)),
+                is_hard_startable: t.is_hard_startable,
                 deadline: t.deadline.map(|dt| self.splice(dt)),
-                priority: None,
+                is_hard_deadline: t.is_hard_deadline,
+                hard_priority: None,
+                soft_priority: None,
                 weight: t.weight.map(|w| (w * 3600.0) as i64),
                 rank: None,
             });
@@ -214,79 +341,101 @@ struct SubSorter {
 #[derive(Debug, PartialEq)]
 struct SubTask {
     startable: Option<i64>,
+    is_hard_startable: bool,
     deadline: Option<i64>,
-    priority: Option<i64>,
+    is_hard_deadline: bool,
+    hard_priority: Option<i64>,
+    soft_priority: Option<i64>,
     weight: Option<i64>,
     rank: Option<usize>,
 }
 
 struct Player {
     id: i32,
-    priority: Option<i64>,
+    hard: Option<i64>,
+    soft: Option<i64>,
 }
 
 impl SubSorter {
     fn exec(&mut self) {
         let mut rank = 0;
-        while !self.entries.is_empty() {
-            if let Some(win) = self.winner() {
+        // latest start is invariant while scheduling: successors are never scheduled first
+        let graph = graph::Graph::new(&self.entries, &self.arrows);
+        let weight = |id| self.map[&id].weight.unwrap_or_default();
+        let hards = graph.latests(|id| self.map[&id].deadline.filter(|_| self.map[&id].is_hard_deadline), weight);
+        let softs = graph.latests(|id| self.map[&id].deadline.filter(|_| !self.map[&id].is_hard_deadline), weight);
+        // predecessors left to each, so that leaves are found without scanning arrows every time
+        let mut indegrees = HashMap::new();
+        let mut succs = HashMap::new();
+        for arw in &self.arrows.arrows {
+            *indegrees.entry(arw.target).or_insert(0) += 1;
+            succs.entry(arw.source).or_insert_with(Vec::new).push(arw.target);
+        }
+        // in the order of entries, for ties to be broken the same way
+        let positions = self.entries.iter().enumerate().map(|(i, id)| (*id, i)).collect::<HashMap<i32, usize>>();
+        let mut leaves = self.entries.iter().enumerate()
+            .filter(|(_, id)| !indegrees.contains_key(id))
+            .map(|(i, id)| (i, *id))
+            .collect::<BTreeSet<(usize, i32)>>();
+        while !leaves.is_empty() {
+            if let Some(win) = self.winner(&leaves, &hards, &softs) {
                 let weight = self.map[&win.id].weight.unwrap_or_default();
                 let edit = self.map.get_mut(&win.id).unwrap();
-                edit.priority = win.priority;
+                edit.hard_priority = win.hard;
+                edit.soft_priority = win.soft;
                 edit.rank = Some(rank);
                 rank += 1;
                 edit.startable = Some(self.cursor);
                 self.cursor += weight;
                 edit.deadline = Some(self.cursor);
-                self.entries.retain(|id| *id != win.id);
-                self.arrows.arrows.retain(|arw| arw.source != win.id);
+                leaves.remove(&(positions[&win.id], win.id));
+                for succ in succs.get(&win.id).into_iter().flatten() {
+                    let indegree = indegrees.get_mut(succ).unwrap();
+                    *indegree -= 1;
+                    if *indegree == 0 {
+                        if let Some(i) = positions.get(succ) {
+                            leaves.insert((*i, *succ));
+                        }
+                    }
+                }
+            } else if let Some(next) = self.next_startable(&leaves) {
+                // jump to the next event instead of ticking
+                self.cursor = next;
             } else {
-                self.cursor += 1;
+                break // possibly a loop
             }
         }
     }
-    fn winner(&self) -> Option<Player> {
-        self.startables().into_iter().map(|id| Player {
+    // hard deadlines go first, and soft ones only order the rest.
+    // soft startables are brought forward only when nothing else can start
+    fn winner(&self,
+        leaves: &BTreeSet<(usize, i32)>,
+        hards: &HashMap<i32, Option<i64>>,
+        softs: &HashMap<i32, Option<i64>>,
+    ) -> Option<Player> {
+        let priority = |latests: &HashMap<i32, Option<i64>>, id| latests.get(&id).copied().flatten().map(|l| self.cursor - l);
+        let player = |id| Player {
             id: id,
-            priority: self.priority(id),
-        }).max_by_key(|player| player.priority)
+            hard: priority(hards, id),
+            soft: priority(softs, id),
+        };
+        self.startables(leaves).map(player).max_by_key(|player| (player.hard, player.soft))
+        .or_else(|| self.soft_waitings(leaves).map(player).max_by_key(|player| (player.hard, player.soft)))
     }
-    fn startables(&self) -> Vec<i32> {
-        self.entries.iter().copied()
-        .filter(|id| self.map[&id].startable.map(|t| t <= self.cursor).unwrap_or(true))
-        .filter(|id| models::Tid::from(*id).is(models::LR::Leaf, &self.arrows))
-        .collect::<Vec<i32>>()
+    fn startables<'a>(&'a self, leaves: &'a BTreeSet<(usize, i32)>) -> impl Iterator<Item = i32> + 'a {
+        leaves.iter().map(|(_, id)| *id)
+        .filter(move |id| self.map[&id].startable.map(|t| t <= self.cursor).unwrap_or(true))
     }
-    fn priority(&self, id: i32) -> Option<i64> {
-        self.paths(id).iter()
-        .map(|path| self.priority_by(path))
-        .max().unwrap_or_default()
+    fn soft_waitings<'a>(&'a self, leaves: &'a BTreeSet<(usize, i32)>) -> impl Iterator<Item = i32> + 'a {
+        leaves.iter().map(|(_, id)| *id)
+        .filter(move |id| !self.map[&id].is_hard_startable && self.map[&id].startable.map(|t| self.cursor < t).unwrap_or(false))
     }
-    fn priority_by(&self, path: &models::Path) -> Option<i64> {
-        let mut cursor = i64::MAX;
-        for id in path.iter().rev() {
-            if let Some(deadline) = self.map[&id].deadline {
-                cursor = min(cursor, deadline)
-            }
-            cursor -= self.map[&id].weight.unwrap_or_default()
-        }
-        if cursor == i64::MAX {
-            return None
-        }
-        Some(self.cursor - cursor)
-    }
-    fn paths(&self, id: i32) -> Vec<models::Path> {
-        let mut paths = models::Tid::from(id).paths_to(models::LR::Root, &self.arrows);
-        for path in &mut paths {
-            while let Some(last) = path.pop() {
-                if self.map[&last].deadline.is_some() {
-                    path.push(last);
-                    break
-                }
-            }
-        }
-        paths.retain(|path| !path.is_empty());
-        paths
+    // only hard startables are left to wait for
+    fn next_startable(&self, leaves: &BTreeSet<(usize, i32)>) -> Option<i64> {
+        leaves.iter()
+        .filter_map(|(_, id)| self.map[&id].startable)
+        .filter(|t| self.cursor < *t)
+        .min()
     }
 }
 
@@ -298,8 +447,11 @@ mod tests {
     fn t_110() {
         let task = SubTask {
             startable: None,
+            is_hard_startable: false,
             deadline: Some(360),
-            priority: None,
+            is_hard_deadline: false,
+            hard_priority: None,
+            soft_priority: None,
             weight: Some(120),
             rank: None,
         };
@@ -316,10 +468,140 @@ mod tests {
         sub.exec();
         assert_eq!(sub.map[&0], SubTask {
             startable: Some(0),
+            is_hard_startable: false,
             deadline: Some(120),
-            priority: Some(-240),
+            is_hard_deadline: false,
+            hard_priority: None,
+            soft_priority: Some(-240),
             weight: Some(120),
             rank: Some(0),
         });
     }
+    #[test]
+    fn t_111() {
+        let task = SubTask {
+            startable: Some(3600),
+            is_hard_startable: true,
+            deadline: None,
+            is_hard_deadline: false,
+            hard_priority: None,
+            soft_priority: None,
+            weight: Some(60),
+            rank: None,
+        };
+        let mut map = HashMap::new();
+        map.insert(0, task);
+        let mut sub = SubSorter {
+            cursor: 0,
+            entries: vec![0],
+            arrows: models::Arrows {
+                arrows: Vec::new(),
+            },
+            map: map,
+        };
+        sub.exec();
+        assert_eq!(sub.map[&0], SubTask {
+            startable: Some(3600),
+            is_hard_startable: true,
+            deadline: Some(3660),
+            is_hard_deadline: false,
+            hard_priority: None,
+            soft_priority: None,
+            weight: Some(60),
+            rank: Some(0),
+        });
+    }
+    #[test]
+    fn t_112() {
+        let task = |deadline: i64, is_hard: bool| SubTask {
+            startable: None,
+            is_hard_startable: false,
+            deadline: Some(deadline),
+            is_hard_deadline: is_hard,
+            hard_priority: None,
+            soft_priority: None,
+            weight: Some(60),
+            rank: None,
+        };
+        let mut map = HashMap::new();
+        map.insert(0, task(60, false));
+        map.insert(1, task(600, true));
+        map.insert(2, task(120, false));
+        let mut sub = SubSorter {
+            cursor: 0,
+            entries: vec![0, 1, 2],
+            arrows: models::Arrows {
+                arrows: Vec::new(),
+            },
+            map: map,
+        };
+        sub.exec();
+        // the hard one first however loose, then the soft ones by their own priorities
+        assert_eq!((sub.map[&1].rank, sub.map[&1].hard_priority, sub.map[&1].soft_priority), (Some(0), Some(-540), None));
+        assert_eq!((sub.map[&0].rank, sub.map[&0].hard_priority, sub.map[&0].soft_priority), (Some(1), None, Some(60)));
+        assert_eq!((sub.map[&2].rank, sub.map[&2].hard_priority, sub.map[&2].soft_priority), (Some(2), None, Some(60)));
+    }
+    #[test]
+    fn t_113() {
+        let task = |startable: i64, is_hard: bool| SubTask {
+            startable: Some(startable),
+            is_hard_startable: is_hard,
+            deadline: None,
+            is_hard_deadline: false,
+            hard_priority: None,
+            soft_priority: None,
+            weight: Some(60),
+            rank: None,
+        };
+        let mut map = HashMap::new();
+        map.insert(0, task(600, true));
+        map.insert(1, task(300, false));
+        let mut sub = SubSorter {
+            cursor: 0,
+            entries: vec![0, 1],
+            arrows: models::Arrows {
+                arrows: Vec::new(),
+            },
+            map: map,
+        };
+        sub.exec();
+        // nothing can start now, so the soft one is brought forward while the hard one is waited for
+        assert_eq!((sub.map[&1].rank, sub.map[&1].startable), (Some(0), Some(0)));
+        assert_eq!((sub.map[&0].rank, sub.map[&0].startable), (Some(1), Some(600)));
+    }
+    #[test]
+    #[ignore] // cargo test --release -- --ignored b_5k
+    fn b_5k() {
+        let n = 5000;
+        let mut map = HashMap::new();
+        let mut arrows = Vec::new();
+        for i in 0..n {
+            map.insert(i, SubTask {
+                startable: Some((i % 50) as i64 * 600),
+                is_hard_startable: true,
+                deadline: if i % 10 == 9 { Some(i as i64 * 3600) } else { None },
+                is_hard_deadline: false,
+                hard_priority: None,
+                soft_priority: None,
+                weight: Some(1800),
+                rank: None,
+            });
+            if i % 10 < 9 {
+                arrows.push(models::Arrow { source: i, target: i + 1 });
+            }
+            if i % 10 < 8 {
+                arrows.push(models::Arrow { source: i, target: i + 2 });
+            }
+        }
+        let mut sub = SubSorter {
+            cursor: 0,
+            entries: map.keys().copied().collect(),
+            arrows: arrows.into(),
+            map: map,
+        };
+        let start = std::time::Instant::now();
+        sub.exec();
+        println!("{} tasks scheduled in {:?}", n, start.elapsed());
+        assert!(sub.map.values().all(|t| t.rank.is_some()));
+    }
 }
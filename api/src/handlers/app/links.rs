@@ -0,0 +1,76 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+
+use crate::broadcast;
+use crate::cache;
+use crate::errors;
+use crate::models;
+use crate::webhook;
+use super::attachments::editable;
+
+#[derive(Serialize)]
+pub struct ResLink {
+    id: i32,
+    url: String,
+    label: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+// text only adds links, so they are removed here
+pub async fn delete(
+    lid: web::Path<i32>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+    cache: web::Data<cache::Cache>,
+    broadcaster: web::Data<broadcast::Broadcaster>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_write()?;
+    let _ = web::block(move || {
+        use crate::schema::links::dsl::{links, id, task, url};
+        use crate::schema::tasks::dsl::{tasks, link};
+
+        let conn = pool.get().unwrap();
+        let lid = lid.into_inner();
+        let _link = links.find(lid).first::<models::Link>(&conn).optional()?.ok_or_else(|| {
+            errors::ServiceError::bad_request(errors::Code::NotFound, format!(
+                "link {}: not found.",
+                lid,
+            ))
+        })?;
+        editable(_link.task, &user, &conn)?;
+        conn.transaction::<_, errors::ServiceError, _>(|| {
+            diesel::delete(links.find(lid)).execute(&conn)?;
+            // the next one takes over as the primary link
+            let next = links
+                .filter(task.eq(&_link.task))
+                .order(id)
+                .select(url)
+                .first::<String>(&conn)
+                .optional()?;
+            diesel::update(tasks.find(_link.task).filter(link.eq(&_link.url)))
+                .set(link.eq(next))
+                .execute(&conn)?;
+            Ok(())
+        })?;
+        webhook::emit(webhook::Event::TaskUpdated, &user, &vec![_link.task], &Vec::new(), &conn)?;
+        broadcaster.send(&broadcast::Message::new(webhook::Event::TaskUpdated, &vec![_link.task], &Vec::new(), &conn)?);
+        Ok(())
+    }).await?;
+
+    cache.invalidate(); // for the primary links on home
+    Ok(HttpResponse::Ok().finish())
+}
+
+impl From<models::Link> for ResLink {
+    fn from(link: models::Link) -> Self {
+        Self {
+            id: link.id,
+            url: link.url,
+            label: link.label,
+            created_at: link.created_at,
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::cache;
+use crate::errors;
+use crate::models;
+use super::home;
+
+#[derive(Serialize)]
+struct ResBody {
+    milestones: Vec<ResMilestone>,
+}
+
+#[derive(Serialize)]
+struct ResMilestone {
+    #[serde(flatten)]
+    task: models::ResTask,
+    count: usize,
+    total_weight: f32,
+    remaining_weight: f32,
+    archived_ratio: f32,
+    projected: Option<DateTime<Utc>>,
+    partial: bool, // some remaining nodes are unscheduled, e.g. of other assignees, so projected is only a lower bound
+    slack: Option<f32>, // hours to spare before the deadline, negative if late, none if partial
+}
+
+// roots of home, each with the work behind it rolled up
+pub async fn milestones(
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+    cache: web::Data<cache::Cache>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    let res_body = web::block(move || {
+        use crate::schema::tasks::dsl::{tasks, id, weight, is_archived};
+
+        let conn = pool.get().unwrap();
+        // scheduled on the user's allocations, so only their own tasks have projections and the rest make it partial
+        let schedules = home::Config::Home.query(&user, &conn, &cache)?
+            .into_iter()
+            .filter_map(|t| t.schedule.as_ref().map(|s| (t.id, s.r)))
+            .collect::<HashMap<i32, DateTime<Utc>>>();
+        let mut _milestones = Vec::new();
+        for root in home::Config::Roots.query(&user, &conn, &cache)? {
+            let ids = user.nodes_to(models::LR::Leaf, &vec![root.id], &conn)?;
+            let nodes = tasks
+                .filter(id.eq_any(&ids))
+                .select((id, weight, is_archived))
+                .load::<Node>(&conn)?;
+            _milestones.push(ResMilestone::new(root, &nodes, &schedules));
+        }
+
+        Ok(ResBody {
+            milestones: _milestones,
+        })
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+type Node = (i32, Option<f32>, bool);
+
+impl ResMilestone {
+    // `nodes` include the root itself
+    fn new(root: models::ResTask, nodes: &Vec<Node>, schedules: &HashMap<i32, DateTime<Utc>>) -> Self {
+        let total = nodes.iter().map(|(_, w, _)| w.unwrap_or_default()).sum::<f32>();
+        let remaining = nodes.iter().filter(|(_, _, a)| !a).map(|(_, w, _)| w.unwrap_or_default()).sum::<f32>();
+        let archived = nodes.iter().filter(|(_, _, a)| *a).count();
+        let projected = nodes.iter()
+            .filter(|(_, _, a)| !a)
+            .filter_map(|(id, _, _)| schedules.get(id))
+            .max()
+            .copied();
+        let partial = nodes.iter().any(|(id, _, a)| !a && !schedules.contains_key(id));
+        let slack = match (root.deadline, projected) {
+            (Some(deadline), Some(projected)) if !partial => Some((deadline - projected).num_minutes() as f32 / 60.0),
+            _ => None,
+        };
+        Self {
+            task: root,
+            count: nodes.len(),
+            total_weight: total,
+            remaining_weight: remaining,
+            archived_ratio: if nodes.is_empty() { 0.0 } else { archived as f32 / nodes.len() as f32 },
+            projected: projected,
+            partial: partial,
+            slack: slack,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn t_new() {
+        let now = Utc.ymd(2021, 2, 22).and_hms(9, 0, 0);
+        let mut root = models::ResTask::default();
+        root.id = 1;
+        root.deadline = Some(now + Duration::hours(10));
+        let nodes = vec![
+            (1, Some(1.0), false),
+            (2, Some(2.0), true),
+            (3, None, true),
+            (4, Some(4.5), false),
+        ];
+        let schedules = vec![
+            (1, now + Duration::hours(12)),
+            (2, now + Duration::hours(20)), // archived, no longer projected
+            (4, now + Duration::hours(6)),
+        ].into_iter().collect::<HashMap<i32, DateTime<Utc>>>();
+        let milestone = ResMilestone::new(root, &nodes, &schedules);
+        assert_eq!(milestone.count, 4);
+        assert_eq!(milestone.total_weight, 7.5);
+        assert_eq!(milestone.remaining_weight, 5.5);
+        assert_eq!(milestone.archived_ratio, 0.5);
+        assert_eq!(milestone.projected, Some(now + Duration::hours(12)));
+        assert_eq!((milestone.partial, milestone.slack), (false, Some(-2.0)));
+        let mut nodes = nodes;
+        nodes.push((5, Some(1.0), false)); // of another assignee
+        let partial = ResMilestone::new(milestone.task, &nodes, &schedules);
+        assert_eq!(partial.projected, Some(now + Duration::hours(12)));
+        assert_eq!((partial.partial, partial.slack), (true, None));
+        let unscheduled = ResMilestone::new(models::ResTask::default(), &vec![(0, None, false)], &HashMap::new());
+        assert_eq!((unscheduled.projected, unscheduled.partial, unscheduled.slack), (None, true, None));
+    }
+}
@@ -0,0 +1,309 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DbError};
+use serde::{Serialize, Deserialize};
+
+use crate::cache;
+use crate::errors;
+use crate::mail;
+use crate::models;
+use crate::schema::{project_members, projects};
+
+#[derive(Deserialize)]
+pub struct ReqProject {
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReqMember {
+    name: String,
+    #[serde(default = "yes")]
+    edit: bool,
+}
+
+#[derive(Serialize)]
+struct ResBody {
+    projects: Vec<ResProject>,
+}
+
+#[derive(Serialize)]
+struct ResProject {
+    id: i32,
+    name: String,
+    owner: String,
+    members: Vec<ResMember>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct ResMember {
+    name: String,
+    edit: bool,
+}
+
+// the projects the user is in
+pub async fn list(
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    let res_body = web::block(move || {
+        use diesel::dsl::exists;
+        use crate::schema::project_members::dsl::{project_members, project, member};
+        use crate::schema::projects::dsl::{projects, id, created_at};
+
+        let conn = pool.get().unwrap();
+        let _projects = projects
+            .filter(exists(project_members
+                .filter(project.eq(id))
+                .filter(member.eq(&user.id))
+            ))
+            .order(created_at)
+            .load::<models::Project>(&conn)?
+            .into_iter().map(|p| ResProject::new(p, &conn)).collect::<Result<Vec<ResProject>, errors::ServiceError>>()?;
+
+        Ok(ResBody {
+            projects: _projects,
+        })
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+pub async fn create(
+    req: web::Json<ReqProject>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_write()?;
+    let res_body = web::block(move || {
+        let conn = pool.get().unwrap();
+        let name = req.into_inner().name;
+        // to be written as %name
+        if name.is_empty() || name.chars().any(|c| c.is_whitespace()) {
+            return Err(errors::ServiceError::bad_request(errors::Code::InvalidName, format!(
+                "%{}: project name must be non-empty without spaces.",
+                name,
+            )).field("name"))
+        }
+        let project = conn.transaction::<_, errors::ServiceError, _>(|| {
+            // names are unique across users, as %name is resolved among every project one is in
+            let project = diesel::insert_into(projects::table).values(&NewProject {
+                name: name.clone(),
+                owner: user.id,
+            }).get_result::<models::Project>(&conn).map_err(|err| match err {
+                DbError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                    errors::ServiceError::bad_request(errors::Code::InvalidName, format!(
+                        "%{}: project name already taken.",
+                        name,
+                    )).field("name")
+                },
+                err => err.into(),
+            })?;
+            diesel::insert_into(project_members::table).values(&models::ProjectMember {
+                project: project.id,
+                member: user.id,
+                edit: true,
+            }).execute(&conn)?;
+            Ok(project)
+        })?;
+
+        ResProject::new(project, &conn)
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+pub async fn delete(
+    pid: web::Path<i32>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+    cache: web::Data<cache::Cache>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_write()?;
+    let _ = web::block(move || {
+        use crate::schema::projects::dsl::projects;
+
+        let conn = pool.get().unwrap();
+        let project = owned(pid.into_inner(), &user, &conn)?;
+        // tasks stay, out of the project
+        diesel::delete(projects.find(project.id)).execute(&conn)?;
+        Ok(())
+    }).await?;
+
+    cache.invalidate(); // for the project names and permissions on homes
+    Ok(HttpResponse::Ok().finish())
+}
+
+// adds a member, or changes their edit permission. new members are told by email
+pub async fn put_member(
+    pid: web::Path<i32>,
+    req: web::Json<ReqMember>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+    cache: web::Data<cache::Cache>,
+    mailer: web::Data<mail::Mailer>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_write()?;
+    let res_body = web::block(move || {
+        use diesel::dsl::{select, exists};
+        use crate::schema::project_members::dsl::{project_members, project, member, edit};
+        use crate::schema::users::dsl::{users, name};
+
+        let conn = pool.get().unwrap();
+        let _project = owned(pid.into_inner(), &user, &conn)?;
+        let req = req.into_inner();
+        let someone = users.filter(name.eq(&req.name)).first::<models::User>(&conn).optional()?.ok_or_else(|| {
+            errors::ServiceError::bad_request(errors::Code::UserNotFound, format!(
+                "@{}: user not found.",
+                req.name,
+            )).field("name")
+        })?;
+        let is_new = !select(exists(project_members
+            .filter(project.eq(&_project.id))
+            .filter(member.eq(&someone.id))
+        )).get_result::<bool>(&conn)?;
+        diesel::insert_into(project_members).values(&models::ProjectMember {
+            project: _project.id,
+            member: someone.id,
+            // the owner keeps editing
+            edit: req.edit || someone.id == _project.owner,
+        })
+        .on_conflict((project, member))
+        .do_update()
+        .set(edit.eq(req.edit || someone.id == _project.owner))
+        .execute(&conn)?;
+        if is_new {
+            // the membership stands even if the mail does not go out
+            if let Err(err) = notify(&someone, &_project, &user, &mailer, &conn) {
+                println!("Share Invitation Error:\n{:#?}", err);
+            }
+        }
+
+        ResProject::new(_project, &conn)
+    }).await?;
+
+    cache.invalidate(); // membership changes what tasks are permitted
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+pub async fn delete_member(
+    path: web::Path<(i32, String)>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+    cache: web::Data<cache::Cache>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_write()?;
+    let res_body = web::block(move || {
+        use crate::schema::project_members::dsl::{project_members, project, member};
+        use crate::schema::users::dsl::{users, id, name};
+
+        let conn = pool.get().unwrap();
+        let (pid, _name) = path.into_inner();
+        let _project = owned(pid, &user, &conn)?;
+        if diesel::delete(project_members
+            .filter(project.eq(&_project.id))
+            .filter(member.ne(&_project.owner))
+            .filter(member.eq_any(users.filter(name.eq(&_name)).select(id)))
+        ).execute(&conn)? == 0 {
+            return Err(errors::ServiceError::bad_request(errors::Code::UserNotFound, format!(
+                "@{}: not a member, or the owner.",
+                _name,
+            )).field("name"))
+        }
+
+        ResProject::new(_project, &conn)
+    }).await?;
+
+    cache.invalidate(); // membership changes what tasks are permitted
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+fn notify(
+    someone: &models::User,
+    project: &models::Project,
+    user: &models::AuthedUser,
+    mailer: &mail::Mailer,
+    conn: &models::Conn,
+) -> Result<(), errors::ServiceError> {
+    use crate::schema::invitations::dsl::{invitations, email, expires_at, locale as invitation_locale};
+    use crate::schema::reminders::dsl::{reminders, locale as reminder_locale};
+    use crate::schema::users::dsl::{users, name};
+
+    // as chosen for reminders, or else as they registered with
+    let locale = match reminders.find(someone.id).select(reminder_locale).first::<String>(conn).optional()? {
+        Some(locale) => Some(locale),
+        None => invitations
+            .filter(email.eq(&someone.email))
+            .order(expires_at.desc())
+            .select(invitation_locale)
+            .first::<String>(conn).optional()?,
+    };
+    let template = mail::Template {
+        name: "share_invitation",
+        locale: locale.unwrap_or_else(|| String::from("en")),
+    };
+    let vars = vec![
+        ("inviter", users.find(user.id).select(name).first::<String>(conn)?),
+        ("project", project.name.clone()),
+    ];
+    mailer.send(&template, &someone.email, &vars)
+}
+
+fn yes() -> bool {
+    true
+}
+
+// only the owner manages the project and its members
+fn owned(
+    pid: i32,
+    user: &models::AuthedUser,
+    conn: &models::Conn,
+) -> Result<models::Project, errors::ServiceError> {
+    use crate::schema::projects::dsl::{projects, owner};
+
+    projects.find(pid).filter(owner.eq(&user.id)).first::<models::Project>(conn).optional()?.ok_or_else(|| {
+        errors::ServiceError::bad_request(errors::Code::NotFound, format!(
+            "project {}: not found, or not the owner.",
+            pid,
+        ))
+    })
+}
+
+#[derive(Insertable)]
+#[table_name = "projects"]
+struct NewProject {
+    name: String,
+    owner: i32,
+}
+
+impl ResProject {
+    fn new(project: models::Project, conn: &models::Conn) -> Result<Self, errors::ServiceError> {
+        use crate::schema::project_members::dsl::{project_members, project as _project, edit};
+        use crate::schema::users::dsl::{users, id, name};
+
+        let members = project_members
+            .filter(_project.eq(&project.id))
+            .inner_join(users)
+            .order(name)
+            .select((name, edit))
+            .load::<(String, bool)>(conn)?;
+        let owner = users.filter(id.eq(&project.owner)).select(name).first::<String>(conn)?;
+
+        Ok(Self {
+            id: project.id,
+            name: project.name,
+            owner: owner,
+            members: members.into_iter().map(|(n, e)| ResMember {
+                name: n,
+                edit: e,
+            }).collect(),
+            created_at: project.created_at,
+        })
+    }
+}
@@ -0,0 +1,137 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::errors;
+use crate::models;
+use crate::schema::reminders;
+use crate::webhook;
+
+#[derive(Deserialize)]
+pub struct ReqBody {
+    is_enabled: bool,
+    #[serde(default = "by_email")]
+    by_email: bool,
+    webhook_url: Option<String>,
+    #[serde(default = "horizon_hours")]
+    horizon_hours: i32,
+    locale: Option<String>,
+}
+
+fn by_email() -> bool { true }
+fn horizon_hours() -> i32 { 24 }
+
+#[derive(Serialize)]
+struct ResBody {
+    is_enabled: bool,
+    by_email: bool,
+    webhook_url: Option<String>,
+    horizon_hours: i32,
+    tz: String,
+    locale: String,
+    last_sent_at: Option<DateTime<Utc>>,
+}
+
+pub async fn get(
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    let res_body = web::block(move || {
+        use crate::schema::reminders::dsl::reminders;
+
+        let conn = pool.get().unwrap();
+        let res_body = match reminders.find(user.id).first::<models::Reminder>(&conn).optional()? {
+            Some(r) => r.into(),
+            None => ResBody {
+                is_enabled: false,
+                by_email: by_email(),
+                webhook_url: None,
+                horizon_hours: horizon_hours(),
+                tz: user.tz.name().into(),
+                locale: String::from("en"),
+                last_sent_at: None,
+            },
+        };
+        Ok(res_body)
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+// opts in or out of digests, in the current timezone
+pub async fn put(
+    req: web::Json<ReqBody>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_write()?;
+    let res_body = web::block(move || {
+        use crate::schema::reminders::dsl::{reminders, owner};
+
+        let conn = pool.get().unwrap();
+        let new = req.into_inner().accept(&user)?;
+        let reminder = diesel::insert_into(reminders).values(&new)
+            .on_conflict(owner)
+            .do_update().set(&new)
+            .get_result::<models::Reminder>(&conn)?;
+
+        Ok(ResBody::from(reminder))
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+impl ReqBody {
+    fn accept(self, user: &models::AuthedUser) -> Result<NewReminder, errors::ServiceError> {
+        if let Some(url) = &self.webhook_url {
+            if let Err(err) = webhook::check_url(url) {
+                return Err(errors::ServiceError::bad_request(errors::Code::InvalidUrl, err).field("webhook_url"))
+            }
+        }
+        if self.is_enabled && !self.by_email && self.webhook_url.is_none() {
+            return Err(errors::ServiceError::bad_request(errors::Code::NoDestination, "nowhere to send digests: enable email or set a webhook URL.").field("by_email"))
+        }
+        if !(1..=24 * 14).contains(&self.horizon_hours) {
+            return Err(errors::ServiceError::bad_request(errors::Code::OutOfRange, "horizon must be from 1 hour to 2 weeks.").field("horizon_hours"))
+        }
+        Ok(NewReminder {
+            owner: user.id,
+            is_enabled: self.is_enabled,
+            by_email: self.by_email,
+            webhook_url: self.webhook_url,
+            horizon_hours: self.horizon_hours,
+            tz: user.tz.name().into(),
+            locale: self.locale.unwrap_or_else(|| String::from("en")),
+        })
+    }
+}
+
+impl From<models::Reminder> for ResBody {
+    fn from(r: models::Reminder) -> Self {
+        Self {
+            is_enabled: r.is_enabled,
+            by_email: r.by_email,
+            webhook_url: r.webhook_url,
+            horizon_hours: r.horizon_hours,
+            tz: r.tz,
+            locale: r.locale,
+            last_sent_at: r.last_sent_at,
+        }
+    }
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "reminders"]
+#[changeset_options(treat_none_as_null = "true")]
+struct NewReminder {
+    owner: i32,
+    is_enabled: bool,
+    by_email: bool,
+    webhook_url: Option<String>,
+    horizon_hours: i32,
+    tz: String,
+    locale: String,
+}
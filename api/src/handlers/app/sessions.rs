@@ -0,0 +1,78 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+
+use crate::errors;
+use crate::models;
+
+#[derive(Serialize)]
+struct ResBody {
+    sessions: Vec<ResSession>,
+}
+
+#[derive(Serialize)]
+struct ResSession {
+    id: uuid::Uuid,
+    is_current: bool,
+    user_agent: Option<String>,
+    ip: Option<String>,
+    last_seen_at: DateTime<Utc>,
+    created_at: DateTime<Utc>,
+}
+
+pub async fn list(
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_session()?;
+    let res_body = web::block(move || {
+        use crate::schema::sessions::dsl::{sessions, owner, expires_at, last_seen_at};
+
+        let conn = pool.get().unwrap();
+        let _sessions = sessions
+            .filter(owner.eq(&user.id))
+            .filter(expires_at.gt(Utc::now()))
+            .order(last_seen_at.desc())
+            .load::<models::Session>(&conn)?
+            .into_iter().map(|s| ResSession {
+                id: s.id,
+                is_current: Some(s.id) == user.session,
+                user_agent: s.user_agent,
+                ip: s.ip,
+                last_seen_at: s.last_seen_at,
+                created_at: s.created_at,
+            }).collect::<Vec<ResSession>>();
+
+        Ok(ResBody {
+            sessions: _sessions,
+        })
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+pub async fn revoke(
+    sid: web::Path<uuid::Uuid>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_session()?;
+    let _ = web::block(move || {
+        use crate::schema::sessions::dsl::{sessions, owner};
+
+        let conn = pool.get().unwrap();
+        let sid = sid.into_inner();
+        if diesel::delete(sessions.find(sid).filter(owner.eq(&user.id))).execute(&conn)? == 0 {
+            return Err(errors::ServiceError::bad_request(errors::Code::NotFound, format!(
+                "session {} not found.",
+                sid,
+            )))
+        }
+        Ok(())
+    }).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
@@ -1,33 +1,41 @@
 use actix_web::{web, HttpResponse};
 use diesel::prelude::*;
 
+use crate::broadcast;
+use crate::cache;
 use crate::errors;
 use crate::models;
+use crate::webhook;
 
 pub async fn star(
     tid: web::Path<i32>,
     user: models::AuthedUser,
     pool: web::Data<models::Pool>,
+    cache: web::Data<cache::Cache>,
+    broadcaster: web::Data<broadcast::Broadcaster>,
 ) -> Result<HttpResponse, errors::ServiceError> {
 
+    user.require_write()?;
     let _ = web::block(move || {
         use diesel::dsl::{select, exists};
-        use crate::schema::permissions::dsl::*;
         use crate::schema::tasks::dsl::{tasks, is_starred};
 
         let conn = pool.get().unwrap();
         let tid = tid.into_inner();
         let task = tasks.find(&tid).first::<models::Task>(&conn)?;
-        if select(exists(permissions
-                .filter(subject.eq(&user.id))
-                .filter(object.eq(&task.assign))
-                .filter(edit)
+        if select(exists(tasks
+                .find(&tid)
+                .filter(user.permits(true))
             )).get_result(&conn)? {
                 diesel::update(&models::Tid::from(tid)).set(is_starred.eq(&!task.is_starred)).execute(&conn)?;
+                let event = if task.is_starred { webhook::Event::TaskUnstarred } else { webhook::Event::TaskStarred };
+                webhook::emit(event, &user, &vec![tid], &Vec::new(), &conn)?;
+                broadcaster.send(&broadcast::Message::new(event, &vec![tid], &Vec::new(), &conn)?);
                 return Ok(())
             }
-        Err(errors::ServiceError::BadRequest("no edit permission.".into()))
+        Err(errors::ServiceError::bad_request(errors::Code::NoEditPermission, "no edit permission.").task_id(tid))
     }).await?;
 
+    cache.invalidate();
     Ok(HttpResponse::Ok().finish())
 }
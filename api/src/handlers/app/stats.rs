@@ -0,0 +1,240 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::cache;
+use crate::errors;
+use crate::models;
+use super::home;
+use super::text::Timescale;
+
+pub const BUCKETS: usize = 12;
+const MAX_BUCKETS: usize = 366;
+
+#[derive(Deserialize)]
+pub struct Q {
+    buckets: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct ResStats {
+    timescale: String,
+    buckets: Vec<ResBucket>,
+    velocity: f32, // archived weight per bucket
+    accuracy: Option<f32>, // estimated weight over allocated hours taken, 1 if exact
+    burndowns: Vec<ResBurndown>,
+}
+
+#[derive(Serialize)]
+struct ResBucket {
+    start: DateTime<Utc>,
+    archived_weight: f32,
+    throughput: usize,
+}
+
+#[derive(Serialize)]
+struct ResBurndown {
+    id: i32,
+    title: String,
+    deadline: DateTime<Utc>,
+    remaining: Vec<f32>, // at the end of each bucket, the last one being now
+    required: Option<f32>, // weight per bucket to be done by the deadline, none if overdue
+}
+
+pub async fn stats(
+    q: web::Query<Q>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+    cache: web::Data<cache::Cache>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    let res_body = web::block(move || {
+        let conn = pool.get().unwrap();
+        compute(q.buckets.unwrap_or(BUCKETS), &user, &conn, &cache)
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+// the last `count` buckets of the user's timescale in their time zone, up to now
+pub fn compute(
+    count: usize,
+    user: &models::AuthedUser,
+    conn: &models::Conn,
+    cache: &cache::Cache,
+) -> Result<ResStats, errors::ServiceError> {
+    use crate::schema::tasks::dsl::{tasks, id, assign, is_archived, weight, startable, created_at, archived_at};
+    use crate::schema::users::dsl::{users, timescale};
+
+    let scale = Timescale::parse(&users.find(user.id).select(timescale).first::<String>(conn)?).unwrap_or(Timescale::Day);
+    let now = Utc::now();
+    let last = scale.floor(&now.with_timezone(&user.tz).naive_local());
+    let starts = (0..count.max(1).min(MAX_BUCKETS) as i32).rev()
+        .map(|i| globalize(&user.tz, &scale.shift(&last, -i)))
+        .collect::<Vec<DateTime<Utc>>>();
+    let ends = starts.iter().skip(1).copied().chain(Some(now)).collect::<Vec<DateTime<Utc>>>();
+
+    let archives = tasks
+        .filter(assign.eq(&user.id))
+        .filter(is_archived)
+        .filter(archived_at.ge(&starts[0]))
+        .order(archived_at)
+        .select((weight, created_at, startable, archived_at))
+        .load::<(Option<f32>, DateTime<Utc>, Option<DateTime<Utc>>, Option<DateTime<Utc>>)>(conn)?
+        .into_iter().filter_map(|(w, c, s, a)| a.map(|a| Archive {
+            weight: w,
+            created_at: c,
+            startable: s,
+            archived_at: a,
+        })).collect::<Vec<Archive>>();
+    let buckets = bucketize(&starts, &archives);
+    let velocity = buckets.iter().map(|b| b.archived_weight).sum::<f32>() / buckets.len() as f32;
+    let sorter = home::Sorter::new(user, conn)?;
+    let accuracy = accuracy(&archives, |l, r| sorter.hours_between(l, r));
+
+    let span = globalize(&user.tz, &scale.shift(&last, 1)) - starts[starts.len() - 1];
+    let mut burndowns = Vec::new();
+    for root in home::Config::Roots.query(user, conn, cache)? {
+        if let Some(deadline) = root.deadline {
+            let ids = user.nodes_to(models::LR::Leaf, &vec![root.id], conn)?;
+            let nodes = tasks
+                .filter(id.eq_any(&ids))
+                .select((weight, created_at, archived_at))
+                .load::<Node>(conn)?;
+            let remaining = remaining(&nodes, &ends);
+            let left = (deadline - now).num_seconds() as f32 / span.num_seconds() as f32;
+            burndowns.push(ResBurndown {
+                id: root.id,
+                title: root.title,
+                deadline: deadline,
+                required: if now < deadline { Some(remaining[remaining.len() - 1] / left) } else { None },
+                remaining: remaining,
+            });
+        }
+    }
+
+    Ok(ResStats {
+        timescale: scale.as_str().into(),
+        buckets: buckets,
+        velocity: velocity,
+        accuracy: accuracy,
+        burndowns: burndowns,
+    })
+}
+
+struct Archive {
+    weight: Option<f32>,
+    created_at: DateTime<Utc>,
+    startable: Option<DateTime<Utc>>,
+    archived_at: DateTime<Utc>,
+}
+
+// weight, created_at, archived_at
+type Node = (Option<f32>, DateTime<Utc>, Option<DateTime<Utc>>);
+
+// a local datetime skipped by DST falls on the transition
+fn globalize(tz: &Tz, local: &NaiveDateTime) -> DateTime<Utc> {
+    tz.from_local_datetime(local).earliest()
+        .or_else(|| tz.from_local_datetime(&(*local + chrono::Duration::hours(1))).earliest())
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(local))
+}
+
+fn bucketize(starts: &Vec<DateTime<Utc>>, archives: &Vec<Archive>) -> Vec<ResBucket> {
+    let mut buckets = starts.iter().map(|start| ResBucket {
+        start: *start,
+        archived_weight: 0.0,
+        throughput: 0,
+    }).collect::<Vec<ResBucket>>();
+    for a in archives {
+        if let Some(bucket) = buckets.iter_mut().rev().find(|b| b.start <= a.archived_at) {
+            bucket.archived_weight += a.weight.unwrap_or_default();
+            bucket.throughput += 1;
+        }
+    }
+    buckets
+}
+
+// work on each is assumed to begin as soon as possible after the previous one,
+// and `archives` are in the order of archived_at
+fn accuracy<F>(archives: &Vec<Archive>, hours_between: F) -> Option<f32>
+where F: Fn(DateTime<Utc>, DateTime<Utc>) -> Option<f32> {
+    let (mut estimated, mut taken) = (0.0, 0.0);
+    let mut prev = None;
+    for a in archives {
+        if let Some(w) = a.weight {
+            let begin = [Some(a.created_at), a.startable, prev].iter().flatten().max().copied().unwrap();
+            estimated += w;
+            taken += hours_between(begin.min(a.archived_at), a.archived_at)?;
+        }
+        prev = Some(a.archived_at);
+    }
+    if taken <= 0.0 { None } else { Some(estimated / taken) }
+}
+
+// weight yet to be archived at each point of time
+fn remaining(nodes: &Vec<Node>, points: &Vec<DateTime<Utc>>) -> Vec<f32> {
+    points.iter().map(|t| {
+        nodes.iter()
+            .filter(|(_, created, _)| created <= t)
+            .filter(|(_, _, archived)| archived.map_or(true, |a| *t < a))
+            .map(|(w, _, _)| w.unwrap_or_default())
+            .sum::<f32>()
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, NaiveDate};
+
+    #[test]
+    fn t_timescale() {
+        let dt = NaiveDate::from_ymd(2021, 2, 24).and_hms(13, 47, 30);
+        let floors = vec![
+            (Timescale::Year, NaiveDate::from_ymd(2021, 1, 1).and_hms(0, 0, 0)),
+            (Timescale::Quarter, NaiveDate::from_ymd(2021, 1, 1).and_hms(0, 0, 0)),
+            (Timescale::Week, NaiveDate::from_ymd(2021, 2, 22).and_hms(0, 0, 0)),
+            (Timescale::Hours, NaiveDate::from_ymd(2021, 2, 24).and_hms(12, 0, 0)),
+            (Timescale::Minutes, NaiveDate::from_ymd(2021, 2, 24).and_hms(13, 45, 0)),
+        ];
+        for (scale, floor) in floors {
+            assert_eq!(scale.floor(&dt), floor);
+        }
+        let month = Timescale::Month.floor(&dt);
+        assert_eq!(Timescale::Month.shift(&month, -2), NaiveDate::from_ymd(2020, 12, 1).and_hms(0, 0, 0));
+        assert_eq!(Timescale::Quarter.shift(&month, 4), NaiveDate::from_ymd(2022, 2, 1).and_hms(0, 0, 0));
+        assert_eq!(Timescale::parse("15m"), Some(Timescale::Minutes));
+        assert_eq!(Timescale::parse("x"), None);
+    }
+    #[test]
+    fn t_stats() {
+        let t0 = Utc.ymd(2021, 2, 22).and_hms(0, 0, 0);
+        let archive = |w: Option<f32>, created: i64, archived: i64| Archive {
+            weight: w,
+            created_at: t0 + Duration::hours(created),
+            startable: None,
+            archived_at: t0 + Duration::hours(archived),
+        };
+        let archives = vec![
+            archive(Some(2.0), 0, 4),
+            archive(None, 1, 5),
+            archive(Some(3.0), 2, 30), // begins after the previous one, at 5
+        ];
+        let starts = vec![t0, t0 + Duration::days(1)];
+        let buckets = bucketize(&starts, &archives);
+        assert_eq!((buckets[0].archived_weight, buckets[0].throughput), (2.0, 2));
+        assert_eq!((buckets[1].archived_weight, buckets[1].throughput), (3.0, 1));
+        let hours = |l: DateTime<Utc>, r: DateTime<Utc>| Some((r - l).num_hours() as f32);
+        assert_eq!(accuracy(&archives, hours), Some(5.0 / 29.0));
+        assert_eq!(accuracy(&archives, |_, _| None), None);
+        let nodes = vec![
+            (Some(1.0), t0, None),
+            (Some(2.0), t0, Some(t0 + Duration::hours(10))),
+            (Some(4.0), t0 + Duration::days(1), None),
+        ];
+        assert_eq!(remaining(&nodes, &vec![t0, t0 + Duration::hours(12), t0 + Duration::days(2)]), vec![3.0, 1.0, 5.0]);
+    }
+}
@@ -1,15 +1,27 @@
 use actix_web::{web, HttpResponse};
-use chrono::{DateTime, NaiveTime, Utc};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc};
 use chrono_tz::Tz;
 use diesel::prelude::*;
 use regex::Regex;
 use serde::{Serialize, Deserialize};
-use std::cmp::max;
+use std::collections::{HashMap, HashSet};
 
+use crate::broadcast;
+use crate::cache;
 use crate::errors;
+use crate::graph;
+use crate::mail;
 use crate::models::{self, Selectable};
-use crate::schema::{tasks, users};
+use crate::schema::{links, projects, tasks, users};
 use crate::utils;
+use crate::webhook;
+use super::home;
+use super::stats;
+
+#[derive(Deserialize)]
+pub struct Q {
+    pub preview: Option<bool>,
+}
 
 #[derive(Deserialize)]
 pub struct ReqBody {
@@ -23,16 +35,53 @@ enum ResBody {
         created: i32,
         updated: i32,
     },
+    Preview {
+        tasks: Vec<models::ResTask>,
+        arrows: Vec<models::Arrow>,
+        diffs: Vec<Diff>,
+    },
+}
+
+#[derive(Serialize)]
+struct Diff {
+    id: i32,
+    fields: Vec<&'static str>,
+    before: models::ResTask,
+    // not part of ResTask, so both sides go here
+    note: Change<Option<String>>,
+    links: Change<Vec<ReqLink>>,
+}
+
+#[derive(Serialize)]
+struct Change<T> {
+    before: T,
+    after: T,
 }
 
 pub async fn text(
+    q: web::Query<Q>,
     req: web::Json<ReqBody>,
     user: models::AuthedUser,
     pool: web::Data<models::Pool>,
+    cache: web::Data<cache::Cache>,
+    broadcaster: web::Data<broadcast::Broadcaster>,
 ) -> Result<HttpResponse, errors::ServiceError> {
 
-    let req = req.into_inner().wash().parse::<Req>()?;
+    let req_body = req.into_inner();
+    let req = req_body.parse()?;
+    let origins = req_body.washed_lines().into_iter().map(|(origin, _)| origin).collect::<Vec<usize>>();
+    let preview = q.preview.unwrap_or_default();
+    if preview && !matches!(req, Req::Tasks(_)) {
+        return Err(errors::ServiceError::bad_request(errors::Code::NotPreviewable, "only tasks can be previewed."))
+    }
+    match &req {
+        Req::Tasks(_) if !preview => user.require_write()?,
+        Req::Command(ReqCommand::User(ReqUser::Modify(req_modify))) => req_modify.authorize(&user)?,
+        _ => (),
+    }
+    let alters_home = req.alters_home() && !preview;
 
+    let _cache = cache.clone();
     let res_body = web::block(move || {
         let conn = pool.get().unwrap();
         match req {
@@ -42,17 +91,25 @@ pub async fn text(
                     ReqCommand::Help              => ResCommand::help(),
                     ReqCommand::User(request)     => request.handle(&user, &conn)?,
                     ReqCommand::Search(condition) => condition.extract(&user, &conn)?,
+                    ReqCommand::Stats             => ResCommand::Stats(stats::compute(stats::BUCKETS, &user, &conn, &_cache)?),
                     ReqCommand::Tutorial          => ResCommand::tutorial(),
-                    ReqCommand::Coffee            => return Err(errors::ServiceError::BadRequest("I'm a teapot.".into())),
+                    ReqCommand::Coffee            => return Err(errors::ServiceError::bad_request(errors::Code::Teapot, "I'm a teapot.")),
                 };
                 Ok(ResBody::Command(res_command))
             },
             Req::Tasks(tasks) => {
-                Ok(tasks.read(&user)?.accept(&user, &conn)?.upsert(&conn)?)
+                let upserter = tasks.read(&user, &origins)?.accept(&user, &conn)?;
+                if preview {
+                    return upserter.preview(&user, &conn)
+                }
+                Ok(upserter.upsert(&user, &broadcaster, &conn)?)
             }
         }
     }).await?;
 
+    if alters_home {
+        cache.invalidate();
+    }
     Ok(HttpResponse::Ok().json(res_body))
 }
 
@@ -62,11 +119,24 @@ pub enum Req {
     Tasks(ReqTasks),
 }
 
+impl Req {
+    // cached homes carry schedules and assignee names
+    fn alters_home(&self) -> bool {
+        match self {
+            Self::Tasks(_) => true,
+            Self::Command(ReqCommand::User(ReqUser::Modify(ReqModify::Allocations(_)))) => true,
+            Self::Command(ReqCommand::User(ReqUser::Modify(ReqModify::Name(_)))) => true,
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum ReqCommand {
     Help,
     User(ReqUser),
     Search(Condition),
+    Stats,
     Tutorial,
     Coffee,
 }
@@ -102,6 +172,7 @@ enum ResCommand {
     Search {
         tasks: Vec<models::ResTask>,
     },
+    Stats(stats::ResStats),
     Tutorial {
         tasks: Vec<models::ResTask>,
     },
@@ -152,9 +223,12 @@ pub struct Condition {
     pub deadline: Range<models::EasyDateTime>,
     pub created_at: Range<models::EasyDateTime>,
     pub updated_at: Range<models::EasyDateTime>,
+    pub archived_at: Range<models::EasyDateTime>,
     pub title: Option<Expression>,
     pub assign: Option<Expression>,
     pub link: Option<Expression>,
+    pub note: Option<Expression>,
+    pub project: Option<Expression>,
 }
 
 #[derive(Debug, Default, PartialEq, PartialOrd)]
@@ -175,15 +249,25 @@ pub enum Expression {
 
 #[derive(Debug, Default, PartialEq)]
 pub struct ReqTasks {
+    // %project
+    pub project: Option<String>,
     pub tasks: Vec<ReqTask>,
 }
 
 #[derive(Debug, Default, PartialEq)]
 pub struct ReqTask {
-    // indent #id joint] * TITLE startable- -deadline $weight @assign [joint link
+    // indent #id joint] * TITLE startable- -deadline $weight @assign [joint links > note
     pub indent: i32,
     pub attribute: Attribute,
-    pub link: Option<String>,
+    pub links: Vec<ReqLink>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReqLink {
+    // url label
+    pub url: String,
+    pub label: Option<String>,
 }
 
 #[derive(Debug, Default, PartialEq)]
@@ -195,7 +279,9 @@ pub struct Attribute {
     pub joint_tail: Option<String>,
     pub assign: Option<String>,
     pub startable: Option<models::EasyDateTime>,
+    pub is_hard_startable: bool,
     pub deadline: Option<models::EasyDateTime>,
+    pub is_hard_deadline: bool,
     pub title: String,
 }
 
@@ -219,6 +305,7 @@ impl ResCommand {
             <!-- /u -t {timescale} <!-- modify user default timescale -->\n\
             <!-- /u -a {h}:{m}-{i}h {h}:{m}-{i}h ... <!-- modify user time allocations -->\n\
             <!-- /s {conditions} <!-- search for tasks by conditions -->\n\
+            <!-- /stats <!-- show archives, burndowns and estimation accuracy by timescale -->\n\
             "
         ))
     }
@@ -232,11 +319,19 @@ impl ResCommand {
                     is_archived: false,
                     is_starred: true,
                     startable: None,
+                    is_hard_startable: false,
                     deadline: None,
+                    is_hard_deadline: false,
                     priority: None,
+                    hard_priority: None,
+                    soft_priority: None,
                     weight: None,
                     link: None, // TODO tutorial external
                     schedule: None,
+                    comments: 0,
+                    project: None,
+                    archived_at: None,
+                    archived_by: None,
                 },
             ],
         }
@@ -285,6 +380,13 @@ impl ReqUser {
 }
 
 impl ReqModify {
+    // credentials are changed only in a session
+    fn authorize(&self, user: &models::AuthedUser) -> Result<(), errors::ServiceError> {
+        match self {
+            Self::Email(_) | Self::Password(_) | Self::Name(_) => user.require_session(),
+            _ => user.require_write(),
+        }
+    }
     fn exec(self,
         user: &models::AuthedUser,
         conn: &models::Conn,
@@ -310,11 +412,17 @@ impl ReqModify {
         };
         let res = match self {
             Self::Email(s) => {
+                if !mail::valid_address(&s) {
+                    return Err(errors::ServiceError::bad_request(errors::Code::InvalidEmail, format!(
+                        "invalid email: {}",
+                        s,
+                    )).field("email"))
+                }
                 if select(exists(users.filter(email.eq(&s)))).get_result(conn)? {
-                    return Err(errors::ServiceError::BadRequest(format!(
+                    return Err(errors::ServiceError::bad_request(errors::Code::AlreadyInUse, format!(
                         "email already in use: {}",
                         s,
-                    )))
+                    )).field("email"))
                 }
                 alt_user.email = Some(s.clone());
                 ResModify::Email(s)
@@ -326,10 +434,10 @@ impl ReqModify {
             },
             Self::Name(s) => {
                 if select(exists(users.filter(name.eq(&s)))).get_result(conn)? {
-                    return Err(errors::ServiceError::BadRequest(format!(
+                    return Err(errors::ServiceError::bad_request(errors::Code::AlreadyInUse, format!(
                         "username already in use: {}",
                         s,
-                    )))
+                    )).field("name"))
                 }
                 alt_user.name = Some(s.clone());
                 ResModify::Name(s)
@@ -341,6 +449,9 @@ impl ReqModify {
             _ => unreachable!(),
         };
         diesel::update(user).set(&alt_user).execute(conn)?;
+        if alt_user.hash.is_some() {
+            models::Session::revoke_all(user.id, user.session, conn)?;
+        }
 
         Ok(res)
     }
@@ -361,18 +472,18 @@ impl PasswordSet {
                     let new_hash = utils::hash(&self.new)?;
                     return Ok(new_hash)
                 }
-                return Err(errors::ServiceError::BadRequest(format!(
+                return Err(errors::ServiceError::bad_request(errors::Code::PasswordMismatch, format!(
                     "new password mismatched with confirmation.",
-                )))
+                )).field("confirmation"))
             }
-            return Err(errors::ServiceError::BadRequest(format!(
+            return Err(errors::ServiceError::bad_request(errors::Code::PasswordTooShort, format!(
                 "password should be at least {} length.",
                 min_password_len,
-            )))
+            )).field("new"))
         }
-        return Err(errors::ServiceError::BadRequest(format!(
+        return Err(errors::ServiceError::bad_request(errors::Code::WrongPassword, format!(
             "current password seems to be wrong.",
-        )))
+        )).field("old"))
     }
 }
 
@@ -391,6 +502,57 @@ impl Timescale {
             Self::Second => "s",
         }
     }
+    pub fn parse(s: &str) -> Option<Self> {
+        vec![
+            Self::Year,
+            Self::Quarter,
+            Self::Month,
+            Self::Week,
+            Self::Day,
+            Self::Hours,
+            Self::Hour,
+            Self::Minutes,
+            Self::Minute,
+            Self::Second,
+        ].into_iter().find(|t| t.as_str() == s)
+    }
+    // the start of the span containing the local datetime
+    pub fn floor(&self, dt: &NaiveDateTime) -> NaiveDateTime {
+        let date = dt.date();
+        let (h, m) = (dt.hour(), dt.minute());
+        match self {
+            Self::Year => NaiveDate::from_ymd(date.year(), 1, 1).and_hms(0, 0, 0),
+            Self::Quarter => NaiveDate::from_ymd(date.year(), date.month0() / 3 * 3 + 1, 1).and_hms(0, 0, 0),
+            Self::Month => NaiveDate::from_ymd(date.year(), date.month(), 1).and_hms(0, 0, 0),
+            Self::Week => (date - Duration::days(date.weekday().num_days_from_monday() as i64)).and_hms(0, 0, 0),
+            Self::Day => date.and_hms(0, 0, 0),
+            Self::Hours => date.and_hms(h / 6 * 6, 0, 0),
+            Self::Hour => date.and_hms(h, 0, 0),
+            Self::Minutes => date.and_hms(h, m / 15 * 15, 0),
+            Self::Minute => date.and_hms(h, m, 0),
+            Self::Second => date.and_hms(h, m, dt.second()),
+        }
+    }
+    // `n` spans later, or earlier if negative, from a floored datetime
+    pub fn shift(&self, dt: &NaiveDateTime, n: i32) -> NaiveDateTime {
+        let months = |k: i32| {
+            let i = dt.year() * 12 + dt.month0() as i32 + k * n;
+            NaiveDate::from_ymd(i.div_euclid(12), i.rem_euclid(12) as u32 + 1, 1).and_hms(0, 0, 0)
+        };
+        let n = n as i64;
+        match self {
+            Self::Year => months(12),
+            Self::Quarter => months(3),
+            Self::Month => months(1),
+            Self::Week => *dt + Duration::weeks(n),
+            Self::Day => *dt + Duration::days(n),
+            Self::Hours => *dt + Duration::hours(6 * n),
+            Self::Hour => *dt + Duration::hours(n),
+            Self::Minutes => *dt + Duration::minutes(15 * n),
+            Self::Minute => *dt + Duration::minutes(n),
+            Self::Second => *dt + Duration::seconds(n),
+        }
+    }
 }
 
 impl ReqAllocation {
@@ -405,9 +567,9 @@ impl ReqAllocation {
                     hours: self.hours,
                 })
             }
-            return Err(errors::ServiceError::BadRequest("please specify 1 to 24 hours.".into()))
+            return Err(errors::ServiceError::bad_request(errors::Code::InvalidAllocation, "please specify 1 to 24 hours."))
         }
-        Err(errors::ServiceError::BadRequest("time notation invalid.".into()))
+        Err(errors::ServiceError::bad_request(errors::Code::InvalidAllocation, "time notation invalid."))
     }
 }
 
@@ -416,15 +578,8 @@ impl Condition {
         user: &models::AuthedUser,
         conn: &models::Conn,
     ) -> Result<ResCommand, errors::ServiceError> {
-        use crate::schema::arrows::dsl::arrows;
-
         let mut res_tasks = self.query(user, conn)?;
-        self.filter_regex(&mut res_tasks)?;
-        if max(self.context.0, self.context.1).is_some() {
-            // TODO load all arrows ?
-            let _arrows: models::Arrows = arrows.load::<models::Arrow>(conn)?.into();
-            self.filter_context(&mut res_tasks, &_arrows);
-        }
+        self.filter_regex(&mut res_tasks, conn)?;
         Ok(ResCommand::Search {
             tasks: res_tasks,
         })
@@ -435,15 +590,11 @@ impl Condition {
     ) -> Result<Vec<models::ResTask>, errors::ServiceError> {
         use diesel::dsl::exists;
         use crate::schema::arrows::dsl::*;
-        use crate::schema::permissions::dsl::*;
         use crate::schema::tasks::dsl::*;
         use crate::schema::users::dsl::{users, name};
 
         let mut query = tasks
-        .filter(exists(permissions
-            .filter(subject.eq(&user.id))
-            .filter(object.eq(assign))
-        ))
+        .filter(user.permits(false))
         .inner_join(users)
         .select(models::SelTask::columns())
         .into_boxed();
@@ -464,6 +615,12 @@ impl Condition {
                 exists(arrows.filter(source.eq(id))).eq(!b)
             )
         }
+        if let Some(tid) = self.context.0 {
+            query = query.filter(id.eq_any(user.nodes_to(models::LR::Root, &vec![tid], conn)?))
+        }
+        if let Some(tid) = self.context.1 {
+            query = query.filter(id.eq_any(user.nodes_to(models::LR::Leaf, &vec![tid], conn)?))
+        }
         if let Some(w) = &self.weight.0 {
             query = query.filter(weight.ge(w))
         }
@@ -494,6 +651,12 @@ impl Condition {
         if let Some(dt) = &self.updated_at.1 {
             query = query.filter(updated_at.le(user.globalize(&dt)?))
         }
+        if let Some(dt) = &self.archived_at.0 {
+            query = query.filter(archived_at.ge(user.globalize(&dt)?))
+        }
+        if let Some(dt) = &self.archived_at.1 {
+            query = query.filter(archived_at.le(user.globalize(&dt)?))
+        }
         if let Some(Expression::Words(words)) = &self.title {
             for w in words {
                 query = query.filter(title.like(format!("%{}%", w)))
@@ -506,7 +669,24 @@ impl Condition {
         }
         if let Some(Expression::Words(words)) = &self.link {
             for w in words {
-                query = query.filter(link.like(format!("%{}%", w)))
+                let w = format!("%{}%", w);
+                query = query.filter(exists(links::table
+                    .filter(links::task.eq(id))
+                    .filter(links::url.like(w.clone()).or(links::label.like(w)))
+                ))
+            }
+        }
+        if let Some(Expression::Words(words)) = &self.note {
+            for w in words {
+                query = query.filter(note.like(format!("%{}%", w)))
+            }
+        }
+        if let Some(Expression::Words(words)) = &self.project {
+            for w in words {
+                query = query.filter(exists(projects::table
+                    .filter(projects::id.nullable().eq(project))
+                    .filter(projects::name.like(format!("%{}%", w)))
+                ))
             }
         }
         Ok(query
@@ -518,6 +698,7 @@ impl Condition {
     }
     fn filter_regex(&self,
         tasks: &mut Vec<models::ResTask>,
+        conn: &models::Conn,
     ) -> Result<(), errors::ServiceError> {
         if let Some(Expression::Regex(regex)) = &self.title {
             let regex = Regex::new(&regex)?;
@@ -528,27 +709,42 @@ impl Condition {
             tasks.retain(|t| regex.is_match(&t.assign))
         }
         if let Some(Expression::Regex(regex)) = &self.link {
+            use crate::schema::links::dsl::{links, task, url, label};
+
             let regex = Regex::new(&regex)?;
-            tasks.retain(|t| regex.is_match(&**t.link.as_ref().unwrap_or(&String::new())));
+            let mut matched = HashSet::new();
+            for (tid, _url, _label) in links
+                .filter(task.eq_any(tasks.iter().map(|t| t.id).collect::<Vec<i32>>()))
+                .select((task, url, label))
+                .load::<(i32, String, Option<String>)>(conn)? {
+                if regex.is_match(&_url) || _label.map_or(false, |l| regex.is_match(&l)) {
+                    matched.insert(tid);
+                }
+            }
+            tasks.retain(|t| matched.contains(&t.id));
         }
-        Ok(())
-    }
-    fn filter_context(&self,
-        tasks: &mut Vec<models::ResTask>,
-        arrows: &models::Arrows,
-    ) {
-        if let Some(id) = self.context.0 {
-            let ids = models::Tid::from(id).nodes_to(models::LR::Root, arrows);
-            tasks.retain(|t| ids.iter().any(|id| *id == t.id))
+        if let Some(Expression::Regex(regex)) = &self.project {
+            let regex = Regex::new(&regex)?;
+            tasks.retain(|t| regex.is_match(&**t.project.as_ref().unwrap_or(&String::new())));
         }
-        if let Some(id) = self.context.1 {
-            let ids = models::Tid::from(id).nodes_to(models::LR::Leaf, arrows);
-            tasks.retain(|t| ids.iter().any(|id| *id == t.id))
+        if let Some(Expression::Regex(regex)) = &self.note {
+            use crate::schema::tasks::dsl::{tasks as _tasks, id, note};
+
+            let regex = Regex::new(&regex)?;
+            // notes are not in the list, so look them up
+            let notes = _tasks
+                .filter(id.eq_any(tasks.iter().map(|t| t.id).collect::<Vec<i32>>()))
+                .select((id, note))
+                .load::<(i32, Option<String>)>(conn)?
+                .into_iter().collect::<HashMap<i32, Option<String>>>();
+            tasks.retain(|t| regex.is_match(&**notes[&t.id].as_ref().unwrap_or(&String::new())));
         }
+        Ok(())
     }
 }
 
 struct Acceptor {
+    project: Option<String>,
     tasks: Vec<TmpTask>,
     arrows: TmpArrows,
 }
@@ -556,19 +752,24 @@ struct Acceptor {
 type TmpArrows =  models::Arrows;
 
 struct TmpTask {
+    line: usize,
     id: Option<i32>,
     title: String,
     assign: Option<String>,
     is_starred: bool,
     startable: Option<DateTime<Utc>>,
+    is_hard_startable: bool,
     deadline: Option<DateTime<Utc>>,
+    is_hard_deadline: bool,
     weight: Option<f32>,
-    link: Option<String>,
+    links: Vec<ReqLink>,
+    note: Option<String>,
 }
 
 impl ReqTasks {
     fn read(self,
         user: &models::AuthedUser,
+        origins: &Vec<usize>,
     ) -> Result<Acceptor, errors::ServiceError> {
         let iter =  self.tasks.iter().enumerate().rev();
         let mut tmp_arrows = Vec::new();
@@ -592,8 +793,14 @@ impl ReqTasks {
                 });
             }
         }
+        let mut lines = Vec::new();
+        let mut cursor = self.project.iter().count();
+        for t in &self.tasks {
+            lines.push(origins.get(cursor).copied().unwrap_or_default());
+            cursor += 1 + t.links.len() + t.note.as_ref().map_or(0, |n| n.split('\n').count());
+        }
         let mut tmp_tasks = Vec::new();
-        for t in self.tasks {
+        for (t, line) in self.tasks.into_iter().zip(lines) {
             let mut startable = None;
             if let Some(dt) = t.attribute.startable {
                 startable = Some(user.globalize(&dt)?)
@@ -603,17 +810,23 @@ impl ReqTasks {
                 deadline = Some(user.globalize(&dt)?)
             }
             tmp_tasks.push(TmpTask {
+                line: line,
                 id: t.attribute.id,
                 title: t.attribute.title,
                 assign: t.attribute.assign,
                 is_starred: t.attribute.is_starred,
+                // only a startable or deadline can be hard
+                is_hard_startable: t.attribute.is_hard_startable && startable.is_some(),
                 startable: startable,
+                is_hard_deadline: t.attribute.is_hard_deadline && deadline.is_some(),
                 deadline: deadline,
                 weight: t.attribute.weight,
-                link: t.link,
+                links: t.links,
+                note: t.note,
             })
         }
         Ok(Acceptor {
+            project: self.project,
             tasks: tmp_tasks,
             arrows: tmp_arrows.into(),
         })
@@ -621,6 +834,7 @@ impl ReqTasks {
 }
 
 struct Upserter {
+    project: Option<Option<models::Project>>,
     tasks: Vec<TmpTaskOk>,
     arrows: TmpArrows,
 }
@@ -631,9 +845,13 @@ struct TmpTaskOk {
     assign: i32,
     is_starred: bool,
     startable: Option<DateTime<Utc>>,
+    is_hard_startable: bool,
     deadline: Option<DateTime<Utc>>,
+    is_hard_deadline: bool,
     weight: Option<f32>,
-    link: Option<String>,
+    links: Vec<ReqLink>,
+    note: Option<String>,
+    project: Option<Option<i32>>,
 }
 
 impl Acceptor {
@@ -644,9 +862,12 @@ impl Acceptor {
 
         self.no_loop()?;
         self.valid_sd()?;
-        self.valid_tid_use()?;
         self.valid_tid(user, conn)?;
-        let assigns = self.valid_assign(user, conn)?;
+        // reported as a loop rather than as wiring, if it is one
+        self.no_loop_through_db(user, conn)?;
+        self.valid_tid_use()?;
+        let project = self.valid_project(user, conn)?;
+        let assigns = self.valid_assign(project.as_ref().and_then(|p| p.as_ref()), user, conn)?;
 
         let tasks = self.tasks.into_iter().zip(assigns.iter()).map(|(t, &a)| TmpTaskOk {
             id: t.id,
@@ -654,37 +875,111 @@ impl Acceptor {
             assign: a,
             is_starred: t.is_starred,
             startable: t.startable,
+            is_hard_startable: t.is_hard_startable,
             deadline: t.deadline,
+            is_hard_deadline: t.is_hard_deadline,
             weight: t.weight,
-            link: t.link,
+            links: t.links,
+            note: t.note,
+            project: project.as_ref().map(|p| p.as_ref().map(|p| p.id)),
         }).collect::<Vec<TmpTaskOk>>();
 
         Ok(Upserter {
+            project: project,
             tasks: tasks,
             arrows: self.arrows,
         })
     }
     fn no_loop(&self) -> Result<(), errors::ServiceError> {
-        if self.arrows.has_cycle() {
-            return Err(errors::ServiceError::BadRequest("loop found.".into()))
+        if let Some(cycle) = graph::Graph::from(&self.arrows).cycle() {
+            let mut labels = cycle.iter().map(|idx| self.label(*idx as usize)).collect::<Vec<String>>();
+            labels.push(labels[0].clone());
+            return Err(errors::ServiceError::bad_request(errors::Code::Loop, format!(
+                "loop found: {}.",
+                labels.join(" -> "),
+            )).line(self.tasks[cycle[0] as usize].line))
         }
         Ok(())
     }
+    // loops closed by arrows already in the database
+    fn no_loop_through_db(&self,
+        user: &models::AuthedUser,
+        conn: &models::Conn,
+    ) -> Result<(), errors::ServiceError> {
+        use crate::schema::arrows::dsl::{arrows, source, target};
+        use crate::schema::tasks::dsl::{tasks, id, title};
+
+        let ids = self.ids();
+        if ids.is_empty() {
+            return Ok(())
+        }
+        let nodes = user.nodes_to(models::LR::Root, &ids, conn)?;
+        // existing tasks in the text by their indices, the others by negative ids
+        let idxs = self.tasks.iter().enumerate()
+            .filter_map(|(idx, t)| t.id.map(|tid| (tid, idx as i32)))
+            .collect::<HashMap<i32, i32>>();
+        let node = |tid: i32| idxs.get(&tid).copied().unwrap_or(-tid);
+        let mut all_arrows = self.arrows.clone();
+        all_arrows.arrows.extend(arrows
+            .filter(source.eq_any(&nodes))
+            .filter(target.eq_any(&nodes))
+            .select((source, target))
+            .load::<models::Arrow>(conn)?
+            .into_iter().map(|arw| models::Arrow {
+                source: node(arw.source),
+                target: node(arw.target),
+            }));
+        if let Some(mut cycle) = graph::Graph::from(&all_arrows).cycle() {
+            // from a line in the text
+            let first = cycle.iter().position(|n| 0 <= *n).unwrap_or_default();
+            cycle.rotate_left(first);
+            let titles = tasks
+                .filter(id.eq_any(cycle.iter().filter(|n| **n < 0).map(|n| -n).collect::<Vec<i32>>()))
+                .select((id, title))
+                .load::<(i32, String)>(conn)?
+                .into_iter().collect::<HashMap<i32, String>>();
+            let mut labels = cycle.iter().map(|n| match *n {
+                n if n < 0 => format!("#{} {}...", -n, titles[&-n].chars().take(8).collect::<String>()),
+                n => self.label(n as usize),
+            }).collect::<Vec<String>>();
+            labels.push(labels[0].clone());
+            let err = errors::ServiceError::bad_request(errors::Code::Loop, format!(
+                "loop found through existing arrows: {}.",
+                labels.join(" -> "),
+            ));
+            return Err(match cycle[0] {
+                n if n < 0 => err.task_id(-n),
+                n => err.line(self.tasks[n as usize].line),
+            })
+        }
+        Ok(())
+    }
+    fn label(&self, idx: usize) -> String {
+        let t = &self.tasks[idx];
+        let title = t.title.chars().take(8).collect::<String>();
+        match t.id {
+            Some(id) => format!("L{} #{} {}...", t.line, id, title),
+            None => format!("L{} {}...", t.line, title),
+        }
+    }
     fn valid_sd(&self) -> Result<(), errors::ServiceError> {
         if let Some(t) = self.tasks.iter()
         .filter(|t| t.deadline.is_some() && t.startable.is_some())
         .find(|t| t.deadline.unwrap() < t.startable.unwrap()) {
-            return Err(errors::ServiceError::BadRequest(format!(
+            return Err(errors::ServiceError::bad_request(errors::Code::DeadlineBeforeStartable, format!(
                 "{}... deadline then startable.",
                 t.title.chars().take(8).collect::<String>(),
-            )))
+            )).line(t.line).field("deadline"))
         }
         Ok(())
     }
     fn valid_tid_use(&self) -> Result<(), errors::ServiceError> {
         self.tid_unique()?;
-        for path in self.arrows.paths() {
-            self.tid_single_by(&path)?;
+        let graph = graph::Graph::from(&self.arrows);
+        for (idx, t) in self.tasks.iter().enumerate() {
+            if t.id.is_some() {
+                self.tid_single_from(idx, &graph.nodes_to(models::LR::Root, &vec![idx as i32]))?;
+            }
         }
         Ok(())
     }
@@ -694,10 +989,10 @@ impl Acceptor {
         let mut last = i32::MIN;
         for id in ids {
             if id == last {
-                return Err(errors::ServiceError::BadRequest(format!(
+                return Err(errors::ServiceError::bad_request(errors::Code::DuplicateId, format!(
                     "#{} appears multiple times.",
                     id,
-                )))
+                )).task_id(id))
             }
             last = id
         }
@@ -706,14 +1001,15 @@ impl Acceptor {
     fn ids(&self) -> Vec<i32> {
         self.tasks.iter().filter_map(|t| t.id).collect::<Vec<i32>>()
     }
-    fn tid_single_by(&self, path: &models::Path) -> Result<(), errors::ServiceError> {
-        let ids = path.iter().filter_map(|idx| self.tasks.get(*idx as usize).unwrap().id).collect::<Vec<i32>>();
-        if 1 < ids.len() {
-            return Err(errors::ServiceError::BadRequest(format!(
-                "#{} -> #{} existing nodes wiring.",
-                ids.get(0).unwrap(),
-                ids.get(1).unwrap(),
-            )))
+    fn tid_single_from(&self, idx: usize, successors: &Vec<i32>) -> Result<(), errors::ServiceError> {
+        if let Some(succ) = successors.iter()
+        .map(|succ| *succ as usize)
+        .find(|succ| *succ != idx && self.tasks[*succ].id.is_some()) {
+            return Err(errors::ServiceError::bad_request(errors::Code::ExistingNodesWiring, format!(
+                "existing nodes wiring: {} -> {}.",
+                self.label(idx),
+                self.label(succ),
+            )).line(self.tasks[idx].line).task_id(self.tasks[succ].id.unwrap()))
         }
         Ok(())
     }
@@ -721,34 +1017,61 @@ impl Acceptor {
         user: &models::AuthedUser,
         conn: &models::Conn,
     ) -> Result<(), errors::ServiceError> {
-        use diesel::dsl::exists;
-        use crate::schema::permissions::dsl::*;
-        use crate::schema::tasks::dsl::{tasks, assign};
+        use crate::schema::tasks::dsl::tasks;
 
-        for id in self.ids() {
+        for t in self.tasks.iter().filter(|t| t.id.is_some()) {
+            let id = t.id.unwrap();
             if tasks
             .find(id)
-            .filter(exists(permissions
-                .filter(subject.eq(&user.id))
-                .filter(object.eq(assign))
-                .filter(edit)
-            ))
+            .filter(user.permits(true))
             .first::<models::Task>(conn)
             .is_err() {
-                return Err(errors::ServiceError::BadRequest(format!(
+                return Err(errors::ServiceError::bad_request(errors::Code::NotFound, format!(
                     "#{}: item not found, or no edit permission.",
                     id,
-                )))
+                )).line(t.line).task_id(id))
             }
         }
         Ok(())
     }
+    // among the projects the user can edit in; `%` alone for none
+    fn valid_project(&self,
+        user: &models::AuthedUser,
+        conn: &models::Conn,
+    ) -> Result<Option<Option<models::Project>>, errors::ServiceError> {
+        use diesel::dsl::exists;
+        use crate::schema::project_members::dsl::{project_members, project, member, edit};
+        use crate::schema::projects::dsl::{projects, id, name};
+
+        match self.project.as_deref() {
+            None => Ok(None),
+            Some("") => Ok(Some(None)),
+            Some(_name) => match projects
+                .filter(name.eq(_name))
+                .filter(exists(project_members
+                    .filter(project.eq(id))
+                    .filter(member.eq(&user.id))
+                    .filter(edit)
+                ))
+                .first::<models::Project>(conn)
+                .optional()? {
+                Some(p) => Ok(Some(Some(p))),
+                None => Err(errors::ServiceError::bad_request(errors::Code::NotFound, format!(
+                    "%{}: project not found, or no edit permission.",
+                    _name,
+                )).field("project")),
+            },
+        }
+    }
+    // fellow members of the project can be assigned as well
     fn valid_assign(&self,
+        _project: Option<&models::Project>,
         user: &models::AuthedUser,
         conn: &models::Conn,
     ) -> Result<Vec<i32>, errors::ServiceError> {
         use diesel::dsl::exists;
         use crate::schema::permissions::dsl::*;
+        use crate::schema::project_members::dsl::{project_members, project, member};
         use crate::schema::users::dsl::{users, id, name};
 
         let mut assigns = Vec::new();
@@ -761,14 +1084,17 @@ impl Acceptor {
                     .filter(subject.eq(&user.id))
                     .filter(object.eq(id))
                     .filter(edit)
-                ))
+                ).or(exists(project_members
+                    .filter(project.nullable().eq(_project.map(|p| p.id)))
+                    .filter(member.eq(id))
+                )))
                 .first::<models::User>(conn) {
                     Ok(someone) => assign = someone.id,
                     Err(_) => {
-                        return Err(errors::ServiceError::BadRequest(format!(
+                        return Err(errors::ServiceError::bad_request(errors::Code::UserNotFound, format!(
                             "@{}: user not found.",
                             _name,
-                        )))
+                        )).line(t.line).field("assign"))
                     },
                 }
             }
@@ -785,9 +1111,13 @@ struct NewTask {
     assign: i32,
     is_starred: bool,
     startable: Option<DateTime<Utc>>,
+    is_hard_startable: bool,
     deadline: Option<DateTime<Utc>>,
+    is_hard_deadline: bool,
     weight: Option<f32>,
     link: Option<String>,
+    note: Option<String>,
+    project: Option<i32>,
 }
 
 #[derive(AsChangeset)]
@@ -797,34 +1127,63 @@ struct AltTask {
     assign: Option<i32>,
     is_starred: Option<bool>,
     startable: Option<Option<DateTime<Utc>>>,
+    is_hard_startable: Option<bool>,
     deadline: Option<Option<DateTime<Utc>>>,
+    is_hard_deadline: Option<bool>,
     weight: Option<Option<f32>>,
     link: Option<Option<String>>,
+    note: Option<Option<String>>,
+    project: Option<Option<i32>>,
+}
+
+#[derive(Insertable)]
+#[table_name = "links"]
+struct NewLink<'a> {
+    task: i32,
+    url: &'a str,
+    label: Option<&'a str>,
 }
 
 impl Upserter {
     fn upsert(mut self,
+        user: &models::AuthedUser,
+        broadcaster: &broadcast::Broadcaster,
         conn: &models::Conn,
     ) -> Result<ResBody, errors::ServiceError> {
+        use diesel::pg::upsert::excluded;
         use crate::schema::arrows::dsl::arrows;
+        use crate::schema::links::dsl::{links, task, url, label};
         use crate::schema::tasks::dsl::tasks;
 
         let mut permanents = Vec::new();
-        let mut created = 0;
-        let mut updated = 0;
+        let mut created = Vec::new();
+        let mut updated = Vec::new();
         for t in self.tasks.into_iter() {
+            let _links = t.links.clone();
             let id = match t.id {
                 None => {
                     let id = diesel::insert_into(tasks).values(&NewTask::from(t)).get_result::<models::Task>(conn)?.id;
-                    created += 1;
+                    created.push(id);
                     id
                 },
                 Some(id) => {
                     diesel::update(tasks.find(id)).set(&AltTask::from(t)).execute(conn)?;
-                    updated += 1;
+                    updated.push(id);
                     id
                 },
             };
+            // links only add up or get relabeled here, and go away one by one
+            for l in &_links {
+                diesel::insert_into(links).values(&NewLink {
+                    task: id,
+                    url: &l.url,
+                    label: l.label.as_deref(),
+                })
+                .on_conflict((task, url))
+                .do_update()
+                .set(label.eq(excluded(label)))
+                .execute(conn)?;
+            }
             permanents.push(id)
         }
         for arw in &mut self.arrows.arrows {
@@ -832,10 +1191,155 @@ impl Upserter {
             arw.target = *permanents.get(arw.target as usize).unwrap();
         }
         diesel::insert_into(arrows).values(&self.arrows.arrows).execute(conn)?;
+        for (event, ids, _arrows) in vec![
+            (webhook::Event::TaskCreated, &created, &Vec::new()),
+            (webhook::Event::TaskUpdated, &updated, &Vec::new()),
+            (webhook::Event::ArrowCreated, &Vec::new(), &self.arrows.arrows),
+        ] {
+            webhook::emit(event, user, ids, _arrows, conn)?;
+            broadcaster.send(&broadcast::Message::new(event, ids, _arrows, conn)?);
+        }
 
         Ok(ResBody::Tasks {
-            created: created,
-            updated: updated,
+            created: created.len() as i32,
+            updated: updated.len() as i32,
+        })
+    }
+    // what upsert would make, without writing
+    fn preview(self,
+        user: &models::AuthedUser,
+        conn: &models::Conn,
+    ) -> Result<ResBody, errors::ServiceError> {
+        use crate::schema::links::dsl::{links, id as lid, task, url, label};
+        use crate::schema::tasks::dsl::{tasks, id, assign, is_archived, note};
+        use crate::schema::users::dsl::{users, id as uid, name};
+
+        // new tasks get negative temporary ids
+        let ids = self.tasks.iter().enumerate()
+        .map(|(idx, t)| t.id.unwrap_or(-(idx as i32) - 1))
+        .collect::<Vec<i32>>();
+        let arrows = self.arrows.arrows.iter().map(|arw| models::Arrow {
+            source: ids[arw.source as usize],
+            target: ids[arw.target as usize],
+        }).collect::<Vec<models::Arrow>>();
+        let befores = tasks
+            .filter(id.eq_any(&ids))
+            .inner_join(users)
+            .select(models::SelTask::columns())
+            .load::<models::SelTask>(conn)?
+            .into_iter().map(|t| (t.id, t.to_res())).collect::<HashMap<i32, models::ResTask>>();
+        let mut notes = tasks
+            .filter(id.eq_any(&ids))
+            .select((id, note))
+            .load::<(i32, Option<String>)>(conn)?
+            .into_iter().map(|(tid, n)| (tid, Change { before: n.clone(), after: n }))
+            .collect::<HashMap<i32, Change<Option<String>>>>();
+        let mut _links = HashMap::<i32, Change<Vec<ReqLink>>>::new();
+        for (tid, _url, _label) in links
+            .filter(task.eq_any(&ids))
+            .order(lid)
+            .select((task, url, label))
+            .load::<(i32, String, Option<String>)>(conn)? {
+            let l = ReqLink { url: _url, label: _label };
+            let change = _links.entry(tid).or_insert_with(|| Change { before: Vec::new(), after: Vec::new() });
+            change.before.push(l.clone());
+            change.after.push(l);
+        }
+        // the same way upsert goes: a lone `>` clears the note, and links only add up or get relabeled
+        for (t, tid) in self.tasks.iter().zip(&ids) {
+            if let (Some(n), Some(change)) = (&t.note, notes.get_mut(tid)) {
+                change.after = Some(n.clone()).filter(|n| !n.trim().is_empty());
+            }
+            if !befores.contains_key(tid) {
+                continue
+            }
+            let change = _links.entry(*tid).or_insert_with(|| Change { before: Vec::new(), after: Vec::new() });
+            for l in &t.links {
+                match change.after.iter_mut().find(|a| a.url == l.url) {
+                    Some(a) => a.label = l.label.clone(),
+                    None => change.after.push(l.clone()),
+                }
+            }
+        }
+        let names = users
+            .filter(uid.eq_any(self.tasks.iter().map(|t| t.assign).collect::<Vec<i32>>()))
+            .select((uid, name))
+            .load::<(i32, String)>(conn)?
+            .into_iter().collect::<HashMap<i32, String>>();
+        let project = self.project;
+        let afters = self.tasks.into_iter().zip(&ids).map(|(t, tid)| models::ResTask {
+            id: *tid,
+            title: t.title,
+            assign: names[&t.assign].clone(),
+            is_archived: befores.get(tid).map(|b| b.is_archived).unwrap_or_default(),
+            is_starred: t.is_starred,
+            startable: t.startable,
+            is_hard_startable: t.is_hard_startable,
+            deadline: t.deadline,
+            is_hard_deadline: t.is_hard_deadline,
+            priority: None,
+            hard_priority: None,
+            soft_priority: None,
+            weight: t.weight,
+            link: t.links.first().map(|l| l.url.clone()).or_else(|| befores.get(tid).and_then(|b| b.link.clone())),
+            schedule: None,
+            comments: befores.get(tid).map(|b| b.comments).unwrap_or_default(),
+            project: match &project {
+                Some(p) => p.as_ref().map(|p| p.name.clone()),
+                None => befores.get(tid).and_then(|b| b.project.clone()),
+            },
+            archived_at: befores.get(tid).and_then(|b| b.archived_at),
+            archived_by: befores.get(tid).and_then(|b| b.archived_by.clone()),
+        }).collect::<Vec<models::ResTask>>();
+        let mut diffs = Vec::new();
+        for after in &afters {
+            if let Some(before) = befores.get(&after.id) {
+                let fields = vec![
+                    ("title", before.title != after.title),
+                    ("assign", before.assign != after.assign),
+                    ("is_starred", before.is_starred != after.is_starred),
+                    ("startable", before.startable != after.startable),
+                    ("is_hard_startable", before.is_hard_startable != after.is_hard_startable),
+                    ("deadline", before.deadline != after.deadline),
+                    ("is_hard_deadline", before.is_hard_deadline != after.is_hard_deadline),
+                    ("weight", before.weight != after.weight),
+                    ("link", before.link != after.link),
+                    ("project", before.project != after.project),
+                ];
+                let note_change = notes.remove(&after.id).unwrap_or(Change { before: None, after: None });
+                let links_change = _links.remove(&after.id).unwrap_or(Change { before: Vec::new(), after: Vec::new() });
+                let fields = fields.into_iter().chain(vec![
+                    ("note", note_change.before != note_change.after),
+                    ("links", links_change.before != links_change.after),
+                ]).filter(|(_, changed)| *changed).map(|(field, _)| field).collect::<Vec<&'static str>>();
+                diffs.push(Diff {
+                    id: after.id,
+                    fields: fields,
+                    before: before.clone(),
+                    note: note_change,
+                    links: links_change,
+                })
+            }
+        }
+        // schedule along with the rest of home
+        let mut res_tasks = tasks
+            .filter(assign.eq(&user.id))
+            .filter(is_archived.eq(false))
+            .filter(id.ne_all(&ids))
+            .inner_join(users)
+            .select(models::SelTask::columns())
+            .load::<models::SelTask>(conn)?
+            .into_iter().map(|t| t.to_res()).collect::<Vec<models::ResTask>>();
+        res_tasks.extend(afters);
+        let mut all_arrows = models::Arrows::among(&res_tasks, conn)?;
+        all_arrows.arrows.extend(arrows.iter().cloned());
+        home::sort(&mut res_tasks, all_arrows, user, conn)?;
+        res_tasks.retain(|t| ids.contains(&t.id));
+
+        Ok(ResBody::Preview {
+            tasks: res_tasks,
+            arrows: arrows,
+            diffs: diffs,
         })
     }
 }
@@ -847,9 +1351,13 @@ impl From<TmpTaskOk> for NewTask {
             assign: tmp.assign,
             is_starred: tmp.is_starred,
             startable: tmp.startable,
+            is_hard_startable: tmp.is_hard_startable,
             deadline: tmp.deadline,
+            is_hard_deadline: tmp.is_hard_deadline,
             weight: tmp.weight,
-            link: tmp.link,
+            link: tmp.links.into_iter().next().map(|l| l.url),
+            note: tmp.note.filter(|n| !n.trim().is_empty()),
+            project: tmp.project.flatten(),
         }
     }
 }
@@ -861,9 +1369,16 @@ impl From<TmpTaskOk> for AltTask {
             assign: Some(tmp.assign),
             is_starred: Some(tmp.is_starred),
             startable: Some(tmp.startable),
+            is_hard_startable: Some(tmp.is_hard_startable),
             deadline: Some(tmp.deadline),
+            is_hard_deadline: Some(tmp.is_hard_deadline),
             weight: Some(tmp.weight),
-            link: Some(tmp.link),
+            // the first link is the primary one, and no lines keep them as they are
+            link: tmp.links.into_iter().next().map(|l| Some(l.url)),
+            // a lone `>` clears the note
+            note: tmp.note.map(|n| Some(n).filter(|n| !n.trim().is_empty())),
+            // no %project line keeps them where they are
+            project: tmp.project,
         }
     }
 }
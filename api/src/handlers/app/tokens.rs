@@ -0,0 +1,155 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::errors;
+use crate::models;
+use crate::schema::tokens;
+use crate::utils;
+
+#[derive(Deserialize)]
+pub struct ReqBody {
+    name: String,
+    #[serde(default)]
+    read_only: bool,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+struct ResBody {
+    tokens: Vec<ResToken>,
+}
+
+#[derive(Serialize)]
+struct ResCreated {
+    token: String, // shown only once
+    info: ResToken,
+}
+
+#[derive(Serialize)]
+struct ResToken {
+    id: i32,
+    name: String,
+    read_only: bool,
+    expires_at: Option<DateTime<Utc>>,
+    last_used_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+}
+
+pub async fn list(
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_session()?;
+    let res_body = web::block(move || {
+        use crate::schema::tokens::dsl::{tokens, owner, created_at};
+
+        let conn = pool.get().unwrap();
+        let _tokens = tokens
+            .filter(owner.eq(&user.id))
+            .order(created_at.desc())
+            .load::<models::Token>(&conn)?
+            .into_iter().map(|t| t.into()).collect::<Vec<ResToken>>();
+
+        Ok(ResBody {
+            tokens: _tokens,
+        })
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+pub async fn create(
+    req: web::Json<ReqBody>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_session()?;
+    let res_body = web::block(move || {
+        let conn = pool.get().unwrap();
+        req.into_inner().accept(&user, &conn)
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+pub async fn revoke(
+    tid: web::Path<i32>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_session()?;
+    let _ = web::block(move || {
+        use crate::schema::tokens::dsl::{tokens, owner};
+
+        let conn = pool.get().unwrap();
+        let tid = tid.into_inner();
+        if diesel::delete(tokens.find(tid).filter(owner.eq(&user.id))).execute(&conn)? == 0 {
+            return Err(errors::ServiceError::bad_request(errors::Code::NotFound, format!(
+                "token {} not found.",
+                tid,
+            )))
+        }
+        Ok(())
+    }).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(Insertable)]
+#[table_name = "tokens"]
+struct NewToken {
+    owner: i32,
+    name: String,
+    hash: String,
+    read_only: bool,
+    tz: String,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl ReqBody {
+    fn accept(self,
+        user: &models::AuthedUser,
+        conn: &models::Conn,
+    ) -> Result<ResCreated, errors::ServiceError> {
+        use crate::schema::tokens::dsl::tokens;
+
+        if self.name.trim().is_empty() {
+            return Err(errors::ServiceError::bad_request(errors::Code::NoTitle, "please name the token.").field("name"))
+        }
+        if self.expires_at.map_or(false, |at| at < Utc::now()) {
+            return Err(errors::ServiceError::bad_request(errors::Code::OutOfRange, "token already expired.").field("expires_at"))
+        }
+        let secret = uuid::Uuid::new_v4().to_simple().to_string();
+        let token = diesel::insert_into(tokens).values(&NewToken {
+            owner: user.id,
+            name: self.name,
+            hash: utils::hash(&secret)?,
+            read_only: self.read_only,
+            tz: user.tz.to_string(),
+            expires_at: self.expires_at,
+        }).get_result::<models::Token>(conn)?;
+
+        Ok(ResCreated {
+            token: format!("{}.{}", token.id, secret),
+            info: token.into(),
+        })
+    }
+}
+
+impl From<models::Token> for ResToken {
+    fn from(token: models::Token) -> Self {
+        Self {
+            id: token.id,
+            name: token.name,
+            read_only: token.read_only,
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+            created_at: token.created_at,
+        }
+    }
+}
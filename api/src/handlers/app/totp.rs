@@ -0,0 +1,184 @@
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use diesel::prelude::*;
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Serialize, Deserialize};
+
+use crate::errors;
+use crate::models;
+use crate::schema::{recovery_codes, totps};
+use crate::totp;
+use crate::utils;
+
+#[derive(Deserialize)]
+pub struct ReqConfirm {
+    code: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReqDisable {
+    password: String,
+}
+
+#[derive(Serialize)]
+struct ResEnroll {
+    secret: String,
+    uri: String,
+}
+
+#[derive(Serialize)]
+struct ResConfirm {
+    recovery_codes: Vec<String>, // shown only once
+}
+
+// starts over any unconfirmed enrollment, 2FA stays off until confirmed
+pub async fn enroll(
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_session()?;
+    let res_body = web::block(move || {
+        use crate::schema::totps::dsl::{totps, owner, is_enabled};
+        use crate::schema::users::dsl::users;
+
+        let conn = pool.get().unwrap();
+        if select_enabled(user.id, &conn)? {
+            return Err(errors::ServiceError::bad_request(errors::Code::AlreadyInUse, "2FA already enabled."))
+        }
+        let email = users.find(user.id).first::<models::User>(&conn)?.email;
+        let secret = totp::new_secret();
+        diesel::delete(totps.filter(owner.eq(&user.id)).filter(is_enabled.eq(false))).execute(&conn)?;
+        diesel::insert_into(totps).values(&NewTotp {
+            owner: user.id,
+            secret: secret.clone(),
+        }).execute(&conn)?;
+
+        Ok(ResEnroll {
+            uri: totp::uri(&secret, &email),
+            secret: secret,
+        })
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+pub async fn confirm(
+    req: web::Json<ReqConfirm>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_session()?;
+    let res_body = web::block(move || {
+        use crate::schema::totps::dsl::{totps, is_enabled};
+
+        let conn = pool.get().unwrap();
+        let _totp = totps.find(user.id).first::<models::Totp>(&conn).map_err(|_| {
+            errors::ServiceError::bad_request(errors::Code::NotFound, "please enroll 2FA first.")
+        })?;
+        if _totp.is_enabled {
+            return Err(errors::ServiceError::bad_request(errors::Code::AlreadyInUse, "2FA already enabled."))
+        }
+        if !totp::verify(&_totp.secret, &req.code, Utc::now().timestamp()) {
+            return Err(errors::ServiceError::bad_request(errors::Code::WrongOtp, "code did not match.").field("code"))
+        }
+        diesel::update(&_totp).set(is_enabled.eq(true)).execute(&conn)?;
+
+        Ok(ResConfirm {
+            recovery_codes: renew_recovery_codes(user.id, &conn)?,
+        })
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+pub async fn disable(
+    req: web::Json<ReqDisable>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_session()?;
+    let _ = web::block(move || {
+        use crate::schema::recovery_codes::dsl::{recovery_codes, owner as rc_owner};
+        use crate::schema::totps::dsl::{totps, owner};
+        use crate::schema::users::dsl::users;
+
+        let conn = pool.get().unwrap();
+        let hash = users.find(user.id).first::<models::User>(&conn)?.hash;
+        if !utils::verify(&hash, &req.password)? {
+            return Err(errors::ServiceError::bad_request(errors::Code::WrongPassword, "current password seems to be wrong.").field("password"))
+        }
+        diesel::delete(totps.filter(owner.eq(&user.id))).execute(&conn)?;
+        diesel::delete(recovery_codes.filter(rc_owner.eq(&user.id))).execute(&conn)?;
+        Ok(())
+    }).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+// the second step of login, `otp` being a current code or an unused recovery code
+pub fn pass(user_id: i32,
+    otp: Option<&str>,
+    now: i64,
+    conn: &models::Conn,
+) -> Result<(), errors::ServiceError> {
+    use crate::schema::recovery_codes::dsl::{recovery_codes, owner};
+    use crate::schema::totps::dsl::{totps, is_enabled};
+
+    let _totp = match totps.find(user_id).filter(is_enabled).first::<models::Totp>(conn) {
+        Ok(_totp) => _totp,
+        Err(_) => return Ok(()),
+    };
+    let otp = otp.ok_or(errors::ServiceError::bad_request(errors::Code::OtpRequired, "2FA code required.").field("otp"))?;
+    if totp::verify(&_totp.secret, otp, now) {
+        return Ok(())
+    }
+    for code in recovery_codes.filter(owner.eq(&user_id)).load::<models::RecoveryCode>(conn)? {
+        if utils::verify(&code.hash, otp.trim())? {
+            diesel::delete(&code).execute(conn)?;
+            return Ok(())
+        }
+    }
+    Err(errors::ServiceError::Unauthorized)
+}
+
+fn select_enabled(user_id: i32, conn: &models::Conn) -> Result<bool, errors::ServiceError> {
+    use diesel::dsl::{select, exists};
+    use crate::schema::totps::dsl::{totps, owner, is_enabled};
+
+    Ok(select(exists(totps.filter(owner.eq(&user_id)).filter(is_enabled))).get_result(conn)?)
+}
+
+fn renew_recovery_codes(user_id: i32, conn: &models::Conn) -> Result<Vec<String>, errors::ServiceError> {
+    use crate::schema::recovery_codes::dsl::{recovery_codes, owner};
+
+    let codes = (0..10).map(|_| {
+        rand::thread_rng().sample_iter(&Alphanumeric).take(10).collect::<String>()
+    }).collect::<Vec<String>>();
+    let mut news = Vec::new();
+    for code in &codes {
+        news.push(NewRecoveryCode {
+            owner: user_id,
+            hash: utils::hash(code)?,
+        })
+    }
+    diesel::delete(recovery_codes.filter(owner.eq(&user_id))).execute(conn)?;
+    diesel::insert_into(recovery_codes).values(&news).execute(conn)?;
+    Ok(codes)
+}
+
+#[derive(Insertable)]
+#[table_name = "totps"]
+struct NewTotp {
+    owner: i32,
+    secret: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "recovery_codes"]
+struct NewRecoveryCode {
+    owner: i32,
+    hash: String,
+}
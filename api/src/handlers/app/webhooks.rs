@@ -0,0 +1,219 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::errors;
+use crate::models;
+use crate::schema::webhooks;
+use crate::webhook;
+
+#[derive(Deserialize)]
+pub struct ReqBody {
+    url: String,
+    #[serde(default)]
+    events: Vec<String>, // empty for all
+}
+
+#[derive(Serialize)]
+struct ResBody {
+    webhooks: Vec<ResWebhook>,
+}
+
+#[derive(Serialize)]
+struct ResCreated {
+    secret: String, // shown only once
+    info: ResWebhook,
+}
+
+#[derive(Serialize)]
+struct ResWebhook {
+    id: i32,
+    url: String,
+    events: Vec<String>,
+    is_active: bool,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct ResDeliveries {
+    deliveries: Vec<ResDelivery>,
+}
+
+#[derive(Serialize)]
+struct ResDelivery {
+    id: i32,
+    event: String,
+    payload: String,
+    attempts: i32,
+    status: Option<i32>,
+    error: Option<String>,
+    next_attempt_at: Option<DateTime<Utc>>,
+    delivered_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+}
+
+pub async fn list(
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    let res_body = web::block(move || {
+        use crate::schema::webhooks::dsl::{webhooks, owner, created_at};
+
+        let conn = pool.get().unwrap();
+        let _webhooks = webhooks
+            .filter(owner.eq(&user.id))
+            .order(created_at.desc())
+            .load::<models::Webhook>(&conn)?
+            .into_iter().map(|w| w.into()).collect::<Vec<ResWebhook>>();
+
+        Ok(ResBody {
+            webhooks: _webhooks,
+        })
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+pub async fn create(
+    req: web::Json<ReqBody>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_write()?;
+    let res_body = web::block(move || {
+        let conn = pool.get().unwrap();
+        req.into_inner().accept(&user, &conn)
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+pub async fn delete(
+    wid: web::Path<i32>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    user.require_write()?;
+    let _ = web::block(move || {
+        use crate::schema::webhooks::dsl::{webhooks, owner};
+
+        let conn = pool.get().unwrap();
+        let wid = wid.into_inner();
+        if diesel::delete(webhooks.find(wid).filter(owner.eq(&user.id))).execute(&conn)? == 0 {
+            return Err(not_found(wid))
+        }
+        Ok(())
+    }).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+// the latest deliveries, to see what receivers got or why they did not
+pub async fn deliveries(
+    wid: web::Path<i32>,
+    user: models::AuthedUser,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    let res_body = web::block(move || {
+        use crate::schema::webhook_deliveries::dsl::{webhook_deliveries, webhook, id};
+        use crate::schema::webhooks::dsl::{webhooks, owner};
+
+        let conn = pool.get().unwrap();
+        let wid = wid.into_inner();
+        let hook = webhooks.find(wid).filter(owner.eq(&user.id)).first::<models::Webhook>(&conn).map_err(|_| not_found(wid))?;
+        let _deliveries = webhook_deliveries
+            .filter(webhook.eq(&hook.id))
+            .order(id.desc())
+            .limit(50)
+            .load::<models::WebhookDelivery>(&conn)?
+            .into_iter().map(|d| d.into()).collect::<Vec<ResDelivery>>();
+
+        Ok(ResDeliveries {
+            deliveries: _deliveries,
+        })
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(res_body))
+}
+
+fn not_found(wid: i32) -> errors::ServiceError {
+    errors::ServiceError::bad_request(errors::Code::NotFound, format!(
+        "webhook {} not found.",
+        wid,
+    ))
+}
+
+#[derive(Insertable)]
+#[table_name = "webhooks"]
+struct NewWebhook {
+    owner: i32,
+    url: String,
+    secret: String,
+    events: Vec<String>,
+}
+
+impl ReqBody {
+    fn accept(self,
+        user: &models::AuthedUser,
+        conn: &models::Conn,
+    ) -> Result<ResCreated, errors::ServiceError> {
+        use crate::schema::webhooks::dsl::webhooks;
+
+        if let Err(err) = webhook::check_url(&self.url) {
+            return Err(errors::ServiceError::bad_request(errors::Code::InvalidUrl, err).field("url"))
+        }
+        let known = webhook::Event::all().iter().map(|e| e.as_str()).collect::<Vec<&str>>();
+        if let Some(unknown) = self.events.iter().find(|e| !known.contains(&e.as_str())) {
+            return Err(errors::ServiceError::bad_request(errors::Code::UnknownEvent, format!(
+                "unknown event {}, expected one of {}.",
+                unknown,
+                known.join(", "),
+            )).field("events"))
+        }
+        let secret = uuid::Uuid::new_v4().to_simple().to_string();
+        let hook = diesel::insert_into(webhooks).values(&NewWebhook {
+            owner: user.id,
+            url: self.url,
+            secret: secret.clone(),
+            events: self.events,
+        }).get_result::<models::Webhook>(conn)?;
+
+        Ok(ResCreated {
+            secret: secret,
+            info: hook.into(),
+        })
+    }
+}
+
+impl From<models::Webhook> for ResWebhook {
+    fn from(hook: models::Webhook) -> Self {
+        Self {
+            id: hook.id,
+            url: hook.url,
+            events: hook.events,
+            is_active: hook.is_active,
+            created_at: hook.created_at,
+        }
+    }
+}
+
+impl From<models::WebhookDelivery> for ResDelivery {
+    fn from(d: models::WebhookDelivery) -> Self {
+        Self {
+            id: d.id,
+            event: d.event,
+            payload: d.payload,
+            attempts: d.attempts,
+            status: d.status,
+            error: d.error,
+            next_attempt_at: d.next_attempt_at,
+            delivered_at: d.delivered_at,
+            created_at: d.created_at,
+        }
+    }
+}
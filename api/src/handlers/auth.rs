@@ -1,10 +1,11 @@
 use actix_identity::Identity;
-use actix_web::{web, HttpResponse};
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
 use chrono_tz::Tz;
 use diesel::prelude::*;
 use serde::{Serialize, Deserialize};
 
 use crate::errors;
+use crate::limiter;
 use crate::models::{self, Selectable};
 use crate::utils;
 
@@ -13,6 +14,7 @@ pub struct ReqBody {
     email: String,
     password: String,
     tz: Tz,
+    otp: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -25,17 +27,32 @@ struct ResBody {
 
 pub async fn login(
     req: web::Json<ReqBody>,
+    http_req: HttpRequest,
     id: Identity,
     pool: web::Data<models::Pool>,
+    limiter: web::Data<limiter::Limiter>,
 ) -> Result<HttpResponse, errors::ServiceError> {
 
-    let authed_user = web::block(move || {
+    let ip = limiter.client_ip(&http_req);
+    limiter.ip(ip.as_deref().unwrap_or_default())?;
+    limiter.account(&req.email)?;
+    limiter.unlocked(&req.email)?;
+    let user_agent = http_req.headers().get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|s| s.to_string());
+    let account = req.email.clone();
+    let session = web::block(move || {
         let conn = pool.get().unwrap();
-        req.into_inner().to_authed(&conn)
-    }).await?;
+        let authed_user = req.into_inner().to_authed(&conn)?;
+        models::Session::open(&authed_user, user_agent, ip, &conn)
+    }).await.map_err(errors::ServiceError::from);
+    if let Err(errors::ServiceError::Unauthorized) = &session {
+        limiter.fail(&account);
+    }
+    let session = session?;
+    limiter.succeed(&account);
 
-    let identity = serde_json::to_string(&authed_user).unwrap();
-    id.remember(identity);
+    id.remember(session.to_string());
     Ok(HttpResponse::Ok().finish())
 }
 
@@ -52,9 +69,21 @@ pub async fn get_me(
     Ok(HttpResponse::Ok().json(&res_body))
 }
 
-pub async fn logout(id: Identity) -> HttpResponse {
+pub async fn logout(
+    id: Identity,
+    pool: web::Data<models::Pool>,
+) -> Result<HttpResponse, errors::ServiceError> {
+
+    if let Some(sid) = id.identity().and_then(|identity| identity.parse::<uuid::Uuid>().ok()) {
+        let _ = web::block(move || {
+            use crate::schema::sessions::dsl::sessions;
+
+            let conn = pool.get().unwrap();
+            diesel::delete(sessions.find(sid)).execute(&conn).map_err(errors::ServiceError::from)
+        }).await?;
+    }
     id.forget();
-    HttpResponse::Ok().finish()
+    Ok(HttpResponse::Ok().finish())
 }
 
 impl ReqBody {
@@ -66,9 +95,12 @@ impl ReqBody {
         .filter(email.eq(&self.email))
         .first::<models::User>(conn) {
             if utils::verify(&user.hash, &self.password)? {
-                return Ok(models::AuthedUser { 
+                super::app::totp::pass(user.id, self.otp.as_deref(), chrono::Utc::now().timestamp(), conn)?;
+                return Ok(models::AuthedUser {
                     id: user.id,
                     tz: self.tz,
+                    session: None,
+                    scope: models::Scope::Session,
                 })
             }
         }
@@ -1,9 +1,11 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
 use chrono_tz::Tz;
 use diesel::prelude::*;
 use serde::Deserialize;
 
 use crate::errors;
+use crate::limiter;
+use crate::mail;
 use crate::models;
 
 #[derive(Deserialize)]
@@ -11,18 +13,36 @@ pub struct ReqBody {
     email: String,
     forgot_pw: bool,
     tz: Tz,
+    locale: Option<String>,
 }
 
 pub async fn invite(
     req: web::Json<ReqBody>,
+    http_req: HttpRequest,
     pool: web::Data<models::Pool>,
+    limiter: web::Data<limiter::Limiter>,
+    mailer: web::Data<mail::Mailer>,
 ) -> Result<HttpResponse, errors::ServiceError> {
 
+    // every invitation sends an email
+    limiter.ip(limiter.client_ip(&http_req).as_deref().unwrap_or_default())?;
+    limiter.account(&req.email)?;
+    // the most preferred language of the browser, unless specified
+    let locale = http_req.headers().get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .map(|tag| tag.split(';').next().unwrap_or_default().trim().to_string())
+        .filter(|tag| !tag.is_empty() && tag != "*");
+
     let _ = web::block(move || {
         let conn = pool.get().unwrap();
-        let invitation: models::Invitation = req.into_inner().accept(&conn)?;
+        let mut req = req.into_inner();
+        if req.locale.is_none() {
+            req.locale = locale;
+        }
+        let invitation: models::Invitation = req.accept(&conn)?;
         dbg!(&invitation);
-        super::_email::send(&invitation)
+        super::_email::send(&invitation, &mailer)
     }).await?;
 
     Ok(HttpResponse::Ok().finish())
@@ -36,12 +56,18 @@ impl ReqBody {
         use crate::schema::invitations::dsl::invitations;
         use crate::schema::users::dsl::{users, email};
 
+        if !mail::valid_address(&self.email) {
+            return Err(errors::ServiceError::bad_request(errors::Code::InvalidEmail, format!(
+                "invalid email: {}",
+                self.email.escape_debug(),
+            )).field("email"))
+        }
         let user_exists: bool = select(exists(users.filter(email.eq(&self.email)))).get_result(conn)?;
         if user_exists && !self.forgot_pw {
-            return Err(errors::ServiceError::BadRequest("user already exists.".into()))
+            return Err(errors::ServiceError::bad_request(errors::Code::UserExists, "user already exists."))
         }
         if !user_exists && self.forgot_pw {
-            return Err(errors::ServiceError::BadRequest("user does not exist yet.".into()))
+            return Err(errors::ServiceError::bad_request(errors::Code::UserNotFound, "user does not exist yet."))
         }
         let invitation: models::Invitation = self.into();
 
@@ -57,6 +83,7 @@ impl From<ReqBody> for models::Invitation {
             expires_at: chrono::Utc::now() + chrono::Duration::hours(1),
             forgot_pw: req.forgot_pw,
             tz: req.tz.to_string(),
+            locale: req.locale.unwrap_or_else(|| String::from("en")),
         }
     }
 }
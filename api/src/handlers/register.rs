@@ -32,6 +32,7 @@ pub async fn register(
             let old_user = users.filter(email.eq(&req.email)).first::<models::User>(&conn)?;
             let alt_user = req.to_alt(&conn)?;
             diesel::update(&old_user).set(&alt_user).execute(&conn)?;
+            models::Session::revoke_all(old_user.id, None, &conn)?;
         } else {
             let new_user = req.to_new(&conn)?;
             let id = diesel::insert_into(users).values(&new_user).get_result::<models::User>(&conn)?.id;
@@ -94,8 +95,8 @@ impl ReqBody {
                 if chrono::Utc::now() < invitation.expires_at {
                     return Ok(())
                 }
-                return Err(errors::ServiceError::BadRequest("invitation expired.".into()))
+                return Err(errors::ServiceError::bad_request(errors::Code::InvitationExpired, "invitation expired."))
             }
-        Err(errors::ServiceError::BadRequest("invitation invalid.".into()))
+        Err(errors::ServiceError::bad_request(errors::Code::InvitationInvalid, "invitation invalid."))
     }
 }
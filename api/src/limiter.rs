@@ -0,0 +1,198 @@
+use actix_web::HttpRequest;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+
+use crate::errors;
+
+// attempts per sliding window and lockouts, kept in the memory of this process and shared by its workers
+pub struct Limiter {
+    config: Config,
+    hits: Mutex<HashMap<String, VecDeque<DateTime<Utc>>>>,
+    failures: Mutex<HashMap<String, Failure>>,
+    swept_at: Mutex<DateTime<Utc>>,
+}
+
+pub struct Config {
+    pub per_ip: usize,
+    pub per_account: usize,
+    pub window: Duration,
+    pub lockout_after: u32,
+    pub lockout: Duration,
+    pub trust_proxy: bool, // whether Forwarded and X-Forwarded-For are set by our own reverse proxy
+}
+
+struct Failure {
+    count: u32,
+    last_at: DateTime<Utc>,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let var = |key: &str, default: i64| {
+            std::env::var(key).ok().and_then(|s| s.parse::<i64>().ok()).unwrap_or(default)
+        };
+        Self {
+            per_ip: var("RATE_LIMIT_PER_IP", 20) as usize,
+            per_account: var("RATE_LIMIT_PER_ACCOUNT", 5) as usize,
+            window: Duration::seconds(var("RATE_LIMIT_WINDOW_SECONDS", 60)),
+            lockout_after: var("LOCKOUT_AFTER_FAILURES", 5) as u32,
+            lockout: Duration::seconds(var("LOCKOUT_SECONDS", 900)),
+            trust_proxy: var("TRUST_PROXY", 0) != 0,
+        }
+    }
+}
+
+impl Limiter {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config: config,
+            hits: Mutex::new(HashMap::new()),
+            failures: Mutex::new(HashMap::new()),
+            swept_at: Mutex::new(Utc::now()),
+        }
+    }
+    // the peer address, or the client address reported by the proxy only if it is trusted
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<String> {
+        if self.config.trust_proxy {
+            if let Some(ip) = req.connection_info().realip_remote_addr().and_then(parse_ip) {
+                return Some(ip.to_string())
+            }
+        }
+        req.peer_addr().map(|addr| addr.ip().to_string())
+    }
+    pub fn ip(&self, ip: &str) -> Result<(), errors::ServiceError> {
+        self.hit(&format!("ip:{}", ip), self.config.per_ip)
+    }
+    pub fn account(&self, account: &str) -> Result<(), errors::ServiceError> {
+        self.hit(&format!("account:{}", account.to_lowercase()), self.config.per_account)
+    }
+    fn hit(&self, key: &str, limit: usize) -> Result<(), errors::ServiceError> {
+        let now = Utc::now();
+        self.sweep(now);
+        let mut hits = self.hits.lock().unwrap();
+        let queue = hits.entry(key.into()).or_default();
+        while queue.front().map_or(false, |at| *at + self.config.window <= now) {
+            queue.pop_front();
+        }
+        if limit <= queue.len() {
+            let retry = *queue.front().unwrap() + self.config.window - now;
+            return Err(errors::ServiceError::TooManyRequests(retry.num_seconds() + 1))
+        }
+        queue.push_back(now);
+        Ok(())
+    }
+    pub fn unlocked(&self, account: &str) -> Result<(), errors::ServiceError> {
+        let now = Utc::now();
+        let failures = self.failures.lock().unwrap();
+        match failures.get(&account.to_lowercase()).and_then(|f| f.locked_until) {
+            Some(until) if now < until => Err(errors::ServiceError::TooManyRequests((until - now).num_seconds() + 1)),
+            _ => Ok(()),
+        }
+    }
+    pub fn fail(&self, account: &str) {
+        let now = Utc::now();
+        let mut failures = self.failures.lock().unwrap();
+        let failure = failures.entry(account.to_lowercase()).or_insert(Failure {
+            count: 0,
+            last_at: now,
+            locked_until: None,
+        });
+        failure.count += 1;
+        failure.last_at = now;
+        if self.config.lockout_after <= failure.count {
+            failure.count = 0;
+            failure.locked_until = Some(now + self.config.lockout);
+        }
+    }
+    pub fn succeed(&self, account: &str) {
+        self.failures.lock().unwrap().remove(&account.to_lowercase());
+    }
+    // evicts expired windows and lockouts, at most once a window
+    fn sweep(&self, now: DateTime<Utc>) {
+        let mut swept_at = self.swept_at.lock().unwrap();
+        if now < *swept_at + self.config.window {
+            return
+        }
+        *swept_at = now;
+        let window = self.config.window;
+        let lockout = self.config.lockout;
+        self.hits.lock().unwrap().retain(|_, queue| queue.back().map_or(false, |at| now < *at + window));
+        self.failures.lock().unwrap().retain(|_, f| {
+            f.locked_until.map_or(false, |until| now < until) || now < f.last_at + lockout
+        });
+    }
+}
+
+// "ip" or "ip:port", as Forwarded and X-Forwarded-For may carry either
+fn parse_ip(s: &str) -> Option<IpAddr> {
+    let s = s.trim();
+    s.parse::<IpAddr>().ok()
+        .or_else(|| s.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| s.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> Limiter {
+        Limiter::new(Config {
+            per_ip: 3,
+            per_account: 2,
+            window: Duration::minutes(1),
+            lockout_after: 3,
+            lockout: Duration::minutes(15),
+            trust_proxy: false,
+        })
+    }
+    #[test]
+    fn t_hit() {
+        let limiter = limiter();
+        assert!((0..3).all(|_| limiter.ip("127.0.0.1").is_ok()));
+        assert!(limiter.ip("127.0.0.1").is_err());
+        assert!(limiter.ip("127.0.0.2").is_ok());
+        assert!(limiter.account("a@example.com").is_ok());
+        assert!(limiter.account("A@example.com").is_ok());
+        assert!(limiter.account("a@example.com").is_err());
+    }
+    #[test]
+    fn t_lockout() {
+        let limiter = limiter();
+        limiter.fail("a@example.com");
+        limiter.fail("a@example.com");
+        assert!(limiter.unlocked("a@example.com").is_ok());
+        limiter.succeed("a@example.com");
+        limiter.fail("a@example.com");
+        limiter.fail("a@example.com");
+        assert!(limiter.unlocked("a@example.com").is_ok());
+        limiter.fail("a@example.com");
+        match limiter.unlocked("a@example.com") {
+            Err(errors::ServiceError::TooManyRequests(retry)) => assert!(0 < retry && retry <= 15 * 60 + 1),
+            _ => panic!(),
+        }
+        assert!(limiter.unlocked("b@example.com").is_ok());
+    }
+    #[test]
+    fn t_sweep() {
+        let limiter = limiter();
+        let past = Utc::now() - Duration::hours(1);
+        limiter.hits.lock().unwrap().insert("ip:127.0.0.1".into(), vec![past].into_iter().collect());
+        limiter.failures.lock().unwrap().insert("a@example.com".into(), Failure {
+            count: 1,
+            last_at: past,
+            locked_until: None,
+        });
+        limiter.fail("b@example.com");
+        *limiter.swept_at.lock().unwrap() = past;
+        assert!(limiter.ip("127.0.0.2").is_ok());
+        assert_eq!(limiter.hits.lock().unwrap().keys().collect::<Vec<_>>(), vec!["ip:127.0.0.2"]);
+        assert_eq!(limiter.failures.lock().unwrap().keys().collect::<Vec<_>>(), vec!["b@example.com"]);
+        assert_eq!(parse_ip("192.0.2.1:8080"), "192.0.2.1".parse().ok());
+        assert_eq!(parse_ip("[2001:db8::1]:443"), "2001:db8::1".parse().ok());
+        assert_eq!(parse_ip("[2001:db8::1]"), "2001:db8::1".parse().ok());
+        assert_eq!(parse_ip("unknown"), None);
+    }
+}
@@ -0,0 +1,454 @@
+use chrono::Utc;
+use sparkpost::transmission;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+
+use crate::errors;
+use crate::utils;
+
+pub struct Mail {
+    pub from: Sender,
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+#[derive(Clone)]
+pub struct Sender {
+    pub addr: String,
+    pub name: String,
+}
+
+// {template_dir}/{locale}/{name}.{subject,html,txt}, with `{{key}}` placeholders
+pub struct Template {
+    pub name: &'static str,
+    pub locale: String,
+}
+
+pub trait Transport: Send + Sync {
+    fn send(&self, mail: &Mail) -> Result<(), errors::ServiceError>;
+}
+
+pub struct Mailer {
+    transport: Box<dyn Transport>,
+    sender: Sender,
+    template_dir: PathBuf,
+}
+
+impl Mailer {
+    pub fn new(transport: Box<dyn Transport>, sender: Sender, template_dir: PathBuf) -> Self {
+        Self {
+            transport: transport,
+            sender: sender,
+            template_dir: template_dir,
+        }
+    }
+    // `{{app}}` is always filled with the sender name
+    pub fn send(&self, template: &Template, to: &str, vars: &Vec<(&str, String)>) -> Result<(), errors::ServiceError> {
+        let mut vars = vars.clone();
+        vars.push(("app", self.sender.name.clone()));
+        self.transport.send(&template.render(&self.template_dir, &self.sender, to, &vars)?)
+    }
+}
+
+// MAIL_TRANSPORT: sparkpost (default), smtp, file or stdout
+pub fn from_env() -> Mailer {
+    let var = |key: &str, default: &str| std::env::var(key).unwrap_or_else(|_| default.into());
+    Mailer::new(
+        transport_from_env(),
+        Sender {
+            addr: utils::env_var("SENDING_EMAIL_ADDRESS"),
+            name: utils::env_var("APP_NAME"),
+        },
+        PathBuf::from(var("TEMPLATE_DIR", "templates")),
+    )
+}
+
+fn transport_from_env() -> Box<dyn Transport> {
+    let var = |key: &str, default: &str| std::env::var(key).unwrap_or_else(|_| default.into());
+    match var("MAIL_TRANSPORT", "sparkpost").as_str() {
+        "smtp" => Box::new(Smtp {
+            host: utils::env_var("SMTP_HOST"),
+            port: var("SMTP_PORT", "587").parse().expect("SMTP_PORT must be a port number"),
+            starttls: var("SMTP_STARTTLS", "true") == "true",
+            credentials: std::env::var("SMTP_USER").ok().map(|user| (user, utils::env_var("SMTP_PASSWORD"))),
+        }),
+        "file" => Box::new(File {
+            dir: PathBuf::from(var("MAIL_DIR", "mails")),
+        }),
+        "stdout" => Box::new(Stdout),
+        _ => Box::new(SparkPost {
+            api_key: utils::env_var("SPARKPOST_API_KEY"),
+        }),
+    }
+}
+
+// a plain local@domain, so that it can go into headers, SMTP commands and paths as is
+pub fn valid_address(s: &str) -> bool {
+    let mut parts = s.splitn(2, '@');
+    let (local, domain) = match (parts.next(), parts.next()) {
+        (Some(local), Some(domain)) => (local, domain),
+        _ => return false,
+    };
+    let atext = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~.".contains(c);
+    s.len() <= 254
+    && !local.is_empty() && local.len() <= 64
+    && local.chars().all(atext)
+    && !local.starts_with('.') && !local.ends_with('.') && !local.contains("..")
+    && domain.contains('.')
+    && domain.split('.').all(|label| {
+        !label.is_empty() && label.len() <= 63
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && !label.starts_with('-') && !label.ends_with('-')
+    })
+}
+
+impl Template {
+    pub fn render(&self,
+        dir: &Path,
+        from: &Sender,
+        to: &str,
+        vars: &Vec<(&str, String)>,
+    ) -> Result<Mail, errors::ServiceError> {
+        let locale = self.fallbacks().into_iter()
+            .find(|locale| dir.join(locale).join(format!("{}.subject", self.name)).is_file())
+            .ok_or_else(|| {
+                println!("Template Not Found: {} in {:?}", self.name, dir);
+                errors::ServiceError::InternalServerError
+            })?;
+        let read = |ext: &str| read_template(&dir.join(&locale).join(format!("{}.{}", self.name, ext)));
+        Ok(Mail {
+            from: from.clone(),
+            to: to.into(),
+            subject: fill(read("subject")?.trim(), vars, false),
+            html: fill(&read("html")?, vars, true),
+            text: fill(&read("txt")?, vars, false),
+        })
+    }
+    // ja-JP, then ja, then en
+    fn fallbacks(&self) -> Vec<String> {
+        let mut locales = vec![self.locale.clone()];
+        if let Some(language) = self.locale.split(|c| c == '-' || c == '_').next() {
+            locales.push(language.to_lowercase())
+        }
+        locales.push(String::from("en"));
+        locales.dedup();
+        // not to escape the template directory
+        locales.retain(|l| !l.is_empty() && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        locales
+    }
+}
+
+fn read_template(path: &Path) -> Result<String, errors::ServiceError> {
+    std::fs::read_to_string(path).map_err(|err| {
+        println!("Template Error: {:?}\n{:#?}", path, err);
+        errors::ServiceError::InternalServerError
+    })
+}
+
+fn fill(template: &str, vars: &Vec<(&str, String)>, escape: bool) -> String {
+    let mut res = template.to_string();
+    for (key, value) in vars {
+        let value = if escape { escape_html(value) } else { value.clone() };
+        res = res.replace(&format!("{{{{{}}}}}", key), &value);
+    }
+    res
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&#39;")
+}
+
+impl Mail {
+    // RFC 5322 message, parts in base64 not to care about line lengths or dots
+    pub fn to_eml(&self) -> String {
+        let boundary = format!("=_{}", uuid::Uuid::new_v4().to_simple());
+        let part = |content_type: &str, body: &str| format!("\
+            --{}\r\n\
+            Content-Type: {}; charset=UTF-8\r\n\
+            Content-Transfer-Encoding: base64\r\n\
+            \r\n\
+            {}\r\n",
+            boundary,
+            content_type,
+            base64::encode(body).as_bytes().chunks(76)
+                .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+                .collect::<Vec<String>>().join("\r\n"),
+        );
+        format!("\
+            From: {} <{}>\r\n\
+            To: <{}>\r\n\
+            Subject: =?UTF-8?B?{}?=\r\n\
+            Date: {}\r\n\
+            MIME-Version: 1.0\r\n\
+            Content-Type: multipart/alternative; boundary=\"{}\"\r\n\
+            \r\n\
+            {}\
+            {}\
+            --{}--\r\n",
+            self.from.name,
+            self.from.addr,
+            self.to,
+            base64::encode(&self.subject),
+            Utc::now().to_rfc2822(),
+            boundary,
+            part("text/plain", &self.text),
+            part("text/html", &self.html),
+            boundary,
+        )
+    }
+}
+
+struct SparkPost {
+    api_key: String,
+}
+
+impl Transport for SparkPost {
+    fn send(&self, mail: &Mail) -> Result<(), errors::ServiceError> {
+        let tm = transmission::Transmission::new(self.api_key.as_str());
+        let mut email = transmission::Message::new(
+            transmission::EmailAddress::new(mail.from.addr.as_str(), mail.from.name.as_str())
+        );
+        let recipient: transmission::Recipient = mail.to.as_str().into();
+        email
+            .add_recipient(recipient)
+            .subject(&mail.subject)
+            .html(&mail.html)
+            .text(&mail.text);
+
+        // note that we only print out the error response from email api
+        match tm.send(&email) {
+            Ok(tm_resp) => match tm_resp {
+                transmission::TransmissionResponse::ApiResponse(resp) => {
+                    println!("SparkPost Response:\n{:#?}", resp);
+                    Ok(())
+                },
+                transmission::TransmissionResponse::ApiError(errors) => {
+                    println!("SparkPost Errors:\n{:#?}", &errors);
+                    Err(errors::ServiceError::InternalServerError)
+                },
+            },
+            Err(req_err) => {
+                println!("SparkPost Request Error:\n{:#?}", req_err);
+                Err(errors::ServiceError::InternalServerError)
+            },
+        }
+    }
+}
+
+struct Smtp {
+    host: String,
+    port: u16,
+    starttls: bool,
+    credentials: Option<(String, String)>,
+}
+
+impl Transport for Smtp {
+    fn send(&self, mail: &Mail) -> Result<(), errors::ServiceError> {
+        self.deliver(mail).map_err(|err| {
+            println!("SMTP Error:\n{:#?}", err);
+            errors::ServiceError::InternalServerError
+        })
+    }
+}
+
+impl Smtp {
+    fn deliver(&self, mail: &Mail) -> io::Result<()> {
+        let ehlo = format!("EHLO {}", utils::env_var("APP_NAME"));
+        let mut tcp = TcpStream::connect((self.host.as_str(), self.port))?;
+        reply(&mut tcp, 220)?;
+        command(&mut tcp, &ehlo, 250)?;
+        if !self.starttls {
+            return self.transact(&mut tcp, mail)
+        }
+        command(&mut tcp, "STARTTLS", 220)?;
+        let other = |err: String| io::Error::new(io::ErrorKind::Other, err);
+        let connector = native_tls::TlsConnector::new().map_err(|e| other(e.to_string()))?;
+        let mut tls = connector.connect(&self.host, tcp).map_err(|e| other(e.to_string()))?;
+        command(&mut tls, &ehlo, 250)?;
+        self.transact(&mut tls, mail)
+    }
+    fn transact<S: Read + Write>(&self, stream: &mut S, mail: &Mail) -> io::Result<()> {
+        if let Some((user, password)) = &self.credentials {
+            let plain = base64::encode(format!("\0{}\0{}", user, password));
+            command(stream, &format!("AUTH PLAIN {}", plain), 235)?;
+        }
+        command(stream, &format!("MAIL FROM:<{}>", mail.from.addr), 250)?;
+        command(stream, &format!("RCPT TO:<{}>", mail.to), 250)?;
+        command(stream, "DATA", 354)?;
+        command(stream, &format!("{}.", mail.to_eml()), 250)?;
+        command(stream, "QUIT", 221)
+    }
+}
+
+fn command<S: Read + Write>(stream: &mut S, line: &str, expected: u16) -> io::Result<()> {
+    stream.write_all(format!("{}\r\n", line).as_bytes())?;
+    stream.flush()?;
+    reply(stream, expected)
+}
+
+// reads a possibly multiline reply byte by byte, not to swallow bytes past it before STARTTLS
+fn reply<S: Read>(stream: &mut S, expected: u16) -> io::Result<()> {
+    let mut byte = [0u8; 1];
+    loop {
+        let mut line = Vec::new();
+        while line.last() != Some(&b'\n') {
+            if stream.read(&mut byte)? == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"))
+            }
+            line.push(byte[0]);
+        }
+        let line = String::from_utf8_lossy(&line);
+        if line.get(3..4) == Some("-") {
+            continue
+        }
+        return match line.get(0..3).and_then(|code| code.parse::<u16>().ok()) {
+            Some(code) if code == expected => Ok(()),
+            _ => Err(io::Error::new(io::ErrorKind::Other, format!("expected {}, got: {}", expected, line.trim_end()))),
+        }
+    }
+}
+
+// drops each mail in a directory, for self-hosting without a mail server and for tests to inspect
+struct File {
+    dir: PathBuf,
+}
+
+impl Transport for File {
+    fn send(&self, mail: &Mail) -> Result<(), errors::ServiceError> {
+        let path = self.dir.join(format!("{}-{}.eml", Utc::now().format("%Y%m%dT%H%M%S%.6f"), uuid::Uuid::new_v4().to_simple()));
+        std::fs::create_dir_all(&self.dir)
+        .and_then(|_| std::fs::write(&path, mail.to_eml()))
+        .map_err(|err| {
+            println!("Mail File Error:\n{:#?}", err);
+            errors::ServiceError::InternalServerError
+        })
+    }
+}
+
+struct Stdout;
+
+impl Transport for Stdout {
+    fn send(&self, mail: &Mail) -> Result<(), errors::ServiceError> {
+        println!("Mail to {}:\n{}\n{}", mail.to, mail.subject, mail.text);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sender() -> Sender {
+        Sender {
+            addr: String::from("noreply@example.com"),
+            name: String::from("app"),
+        }
+    }
+    fn mail() -> Mail {
+        Mail {
+            from: sender(),
+            to: String::from("someone@example.com"),
+            subject: String::from("招待"),
+            html: String::from("<b>key</b>\n.\n"),
+            text: String::from("key\n.\n"),
+        }
+    }
+    #[test]
+    fn t_file() {
+        let dir = std::env::temp_dir().join(format!("mails-{}", uuid::Uuid::new_v4()));
+        let file = File { dir: dir.clone() };
+        file.send(&mail()).unwrap();
+        let captured = std::fs::read_dir(&dir).unwrap().map(|entry| {
+            std::fs::read_to_string(entry.unwrap().path()).unwrap()
+        }).collect::<Vec<String>>();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(captured.len(), 1);
+        assert!(captured[0].starts_with("From: app <noreply@example.com>\r\nTo: <someone@example.com>\r\n"));
+        assert!(captured[0].contains(&format!("Subject: =?UTF-8?B?{}?=\r\n", base64::encode("招待"))));
+        assert!(captured[0].contains("Content-Type: multipart/alternative; boundary="));
+        assert!(captured[0].contains(&format!("text/plain; charset=UTF-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n", base64::encode("key\n.\n"))));
+        assert!(captured[0].contains(&format!("text/html; charset=UTF-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n", base64::encode("<b>key</b>\n.\n"))));
+        assert!(captured[0].ends_with("--\r\n"));
+    }
+    #[test]
+    fn t_render() {
+        let dir = std::env::temp_dir().join(format!("templates-{}", uuid::Uuid::new_v4()));
+        for (locale, subject) in &[("en", "Hi {{name}}\n"), ("ja", "{{name}} さん\n")] {
+            std::fs::create_dir_all(dir.join(locale)).unwrap();
+            std::fs::write(dir.join(locale).join("hello.subject"), subject).unwrap();
+            std::fs::write(dir.join(locale).join("hello.html"), "<p>{{name}}</p>").unwrap();
+            std::fs::write(dir.join(locale).join("hello.txt"), "{{name}}").unwrap();
+        }
+        let render = |name: &'static str, locale: &str| Template { name: name, locale: locale.into() }
+            .render(&dir, &sender(), "someone@example.com", &vec![("name", String::from("<Tom & Jerry>"))]);
+        let ja = render("hello", "ja-JP").unwrap();
+        let en = render("hello", "../en").unwrap();
+        let missing = render("missing", "en");
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(ja.subject, "<Tom & Jerry> さん");
+        assert_eq!(ja.html, "<p>&lt;Tom &amp; Jerry&gt;</p>");
+        assert_eq!(ja.text, "<Tom & Jerry>");
+        assert_eq!(en.subject, "Hi <Tom & Jerry>");
+        assert!(missing.is_err());
+    }
+    #[test]
+    fn t_render_templates() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("templates");
+        let vars = vec![
+            ("app", String::from("app")),
+            ("inviter", String::from("<alice>")),
+            ("project", String::from("proj")),
+        ];
+        for locale in &["en", "ja"] {
+            let share = Template { name: "share_invitation", locale: locale.to_string() }
+                .render(&dir, &sender(), "someone@example.com", &vars).unwrap();
+            assert!(share.subject.contains("<alice>") && share.subject.contains("%proj"));
+            assert!(share.text.contains("<alice>") && share.text.contains("%proj"));
+            assert!(share.html.contains("&lt;alice&gt;"));
+            assert!(!format!("{}{}{}", share.subject, share.text, share.html).contains("{{"));
+        }
+        let vars = vec![
+            ("app", String::from("app")),
+            ("count", String::from("2")),
+            ("until", String::from("2021/02/11 Thu 09:00")),
+            ("tz", String::from("Asia/Tokyo")),
+            ("tasks", String::from("[OVERDUE] #1 <task>")),
+        ];
+        for locale in &["en", "ja"] {
+            let reminder = Template { name: "deadline_reminder", locale: locale.to_string() }
+                .render(&dir, &sender(), "someone@example.com", &vars).unwrap();
+            assert!(reminder.subject.contains('2'));
+            assert!(reminder.html.contains("#1 &lt;task&gt;"));
+            assert!(!format!("{}{}{}", reminder.subject, reminder.text, reminder.html).contains("{{"));
+        }
+    }
+    #[test]
+    fn t_valid_address() {
+        assert!(valid_address("someone@example.com"));
+        assert!(valid_address("some.one+tag@mail.example.co.jp"));
+        assert!(!valid_address("someone@example.com>\r\nBcc: <other@example.com"));
+        assert!(!valid_address("someone@example.com\nRCPT TO:<other@example.com>"));
+        assert!(!valid_address("../someone@example.com"));
+        assert!(!valid_address("someone"));
+        assert!(!valid_address("@example.com"));
+        assert!(!valid_address("someone@localhost"));
+        assert!(!valid_address("some@one@example.com"));
+        assert!(!valid_address("someone@-example.com"));
+    }
+    #[test]
+    fn t_reply() {
+        let mut ok = io::Cursor::new(b"250-smtp.example.com\r\n250-PIPELINING\r\n250 STARTTLS\r\n".to_vec());
+        let mut ng = io::Cursor::new(b"550 no such user\r\n".to_vec());
+        let mut eof = io::Cursor::new(b"250-smtp".to_vec());
+        assert!(reply(&mut ok, 250).is_ok());
+        assert!(reply(&mut ng, 250).is_err());
+        assert!(reply(&mut eof, 250).is_err());
+    }
+}
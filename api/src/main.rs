@@ -7,11 +7,19 @@ use actix_web::{middleware, web, App, HttpServer};
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
 
+mod broadcast;
+mod cache;
 mod errors;
+mod graph;
 mod handlers;
+mod limiter;
+mod mail;
 mod models;
+mod reminder;
 mod schema;
+mod totp;
 mod utils;
+mod webhook;
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
@@ -27,9 +35,28 @@ async fn main() -> std::io::Result<()> {
             utils::env_var("DATABASE_URL")
         )).expect("Failed to create pool.");
 
+    let cache = web::Data::new(cache::Cache::default());
+    let limiter = web::Data::new(limiter::Limiter::new(limiter::Config::from_env()));
+    let mailer = web::Data::new(mail::from_env());
+    let broadcaster = web::Data::new(broadcast::Broadcaster::default());
+    reminder::spawn(pool.clone(), mailer.clone());
+    webhook::spawn(pool.clone());
+    let _broadcaster = broadcaster.clone();
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(std::time::Duration::from_secs(30));
+        loop {
+            interval.tick().await;
+            _broadcaster.ping();
+        }
+    });
+
     HttpServer::new(move || {
         App::new()
         .data(pool.clone())
+        .app_data(cache.clone())
+        .app_data(limiter.clone())
+        .app_data(mailer.clone())
+        .app_data(broadcaster.clone())
         .wrap(middleware::Logger::default())
         .wrap(Cors::permissive()) // TODO tighten for production
         .wrap(IdentityService::new(
@@ -37,7 +64,7 @@ async fn main() -> std::io::Result<()> {
             .name("auth")
             .path("/")
             // .domain(utils::env_var("COOKIE_DOMAIN").as_str()) // TODO if cross domain
-            .max_age(86400)
+            .max_age(models::Session::ttl().num_seconds())
             .secure(utils::env_var("API_PROTOCOL") == "https") // TODO https
         ))
         .data(web::JsonConfig::default().limit(4096))
@@ -56,15 +83,16 @@ async fn main() -> std::io::Result<()> {
             .service(web::scope("/app")
                 .wrap_fn(|req, srv| {
                     use actix_identity::RequestIdentity;
-                    if req.get_identity().is_some() {
+                    // bearer tokens are verified by the AuthedUser extractor
+                    if req.get_identity().is_some() || models::AuthedUser::bearer(req.headers()).is_some() {
                         use actix_service::Service;
                         srv.call(req)
                     } else {
-                        use actix_web::{dev, HttpResponse};
+                        use actix_web::{dev, ResponseError};
                         use futures::future::{ok, Either};
                         Either::Right(ok(dev::ServiceResponse::new(
                             req.into_parts().0,
-                            HttpResponse::Unauthorized().finish()
+                            errors::ServiceError::Unauthorized.error_response()
                         )))
                     }
                 })
@@ -79,6 +107,9 @@ async fn main() -> std::io::Result<()> {
 
 fn auth_protected(cfg: &mut web::ServiceConfig) {
     cfg
+    .service(web::resource("/events")
+        .route(web::get().to(handlers::app::events::events))
+    )
     .service(web::resource("/tasks")
         .route(web::get().to(handlers::app::home::home))
         .route(web::post().to(handlers::app::text::text))
@@ -87,5 +118,71 @@ fn auth_protected(cfg: &mut web::ServiceConfig) {
     .service(web::resource("/task/{tid}")
         .route(web::get().to(handlers::app::focus::focus))
         .route(web::put().to(handlers::app::star::star))
+    )
+    .service(web::resource("/task/{tid}/attachments")
+        .app_data(web::PayloadConfig::new(handlers::app::attachments::max_bytes()))
+        .route(web::post().to(handlers::app::attachments::upload))
+    )
+    .service(web::resource("/attachment/{id}")
+        .route(web::get().to(handlers::app::attachments::download))
+        .route(web::delete().to(handlers::app::attachments::delete))
+    )
+    .service(web::resource("/link/{id}")
+        .route(web::delete().to(handlers::app::links::delete))
+    )
+    .service(web::resource("/task/{tid}/comments")
+        .route(web::get().to(handlers::app::comments::list))
+        .route(web::post().to(handlers::app::comments::post))
+    )
+    .service(web::resource("/milestones")
+        .route(web::get().to(handlers::app::milestones::milestones))
+    )
+    .service(web::resource("/projects")
+        .route(web::get().to(handlers::app::projects::list))
+        .route(web::post().to(handlers::app::projects::create))
+    )
+    .service(web::resource("/project/{id}")
+        .route(web::delete().to(handlers::app::projects::delete))
+    )
+    .service(web::resource("/project/{id}/members")
+        .route(web::put().to(handlers::app::projects::put_member))
+    )
+    .service(web::resource("/project/{id}/member/{name}")
+        .route(web::delete().to(handlers::app::projects::delete_member))
+    )
+    .service(web::resource("/reminder")
+        .route(web::get().to(handlers::app::reminder::get))
+        .route(web::put().to(handlers::app::reminder::put))
+    )
+    .service(web::resource("/stats")
+        .route(web::get().to(handlers::app::stats::stats))
+    )
+    .service(web::resource("/sessions")
+        .route(web::get().to(handlers::app::sessions::list))
+    )
+    .service(web::resource("/session/{id}")
+        .route(web::delete().to(handlers::app::sessions::revoke))
+    )
+    .service(web::resource("/totp")
+        .route(web::post().to(handlers::app::totp::enroll))
+        .route(web::put().to(handlers::app::totp::confirm))
+        .route(web::delete().to(handlers::app::totp::disable))
+    )
+    .service(web::resource("/tokens")
+        .route(web::get().to(handlers::app::tokens::list))
+        .route(web::post().to(handlers::app::tokens::create))
+    )
+    .service(web::resource("/token/{id}")
+        .route(web::delete().to(handlers::app::tokens::revoke))
+    )
+    .service(web::resource("/webhooks")
+        .route(web::get().to(handlers::app::webhooks::list))
+        .route(web::post().to(handlers::app::webhooks::create))
+    )
+    .service(web::resource("/webhook/{id}")
+        .route(web::delete().to(handlers::app::webhooks::delete))
+    )
+    .service(web::resource("/webhook/{id}/deliveries")
+        .route(web::get().to(handlers::app::webhooks::deliveries))
     );
 }
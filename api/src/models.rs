@@ -1,15 +1,16 @@
-use actix_web::{dev::Payload, Error, FromRequest, HttpRequest};
-use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use actix_web::{dev::Payload, http::header, web, Error, FromRequest, HttpRequest};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
 use chrono_tz::Tz;
 use diesel::prelude::*;
-use diesel::{r2d2::ConnectionManager, PgConnection};
-use futures::future::{err, ok, Ready};
+use diesel::{dsl::sql, expression::SqlLiteral, r2d2::ConnectionManager, sql_types::{BigInt, Bool, Nullable, Text}, PgConnection};
+use futures::future::{err, FutureExt, LocalBoxFuture};
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 use std::ops::Not;
 
 use crate::errors;
 use crate::schema::*;
+use crate::utils;
 
 pub type Pool = r2d2::Pool<ConnectionManager<PgConnection>>;
 pub type Conn = r2d2::PooledConnection<ConnectionManager<PgConnection>>;
@@ -23,7 +24,7 @@ pub struct Allocation {
     pub hours: i32,
 }
 
-#[derive(Queryable, Insertable, Debug, PartialEq, Clone)]
+#[derive(Queryable, Insertable, Serialize, Debug, PartialEq, Clone)]
 pub struct Arrow {
     pub source: i32,
     pub target: i32,
@@ -36,6 +37,7 @@ pub struct Invitation {
     pub expires_at: DateTime<Utc>,
     pub forgot_pw: bool,
     pub tz: String,
+    pub locale: String,
 }
 
 #[derive(Queryable, Identifiable, Insertable)]
@@ -46,6 +48,25 @@ pub struct Permission {
     pub edit: bool,
 }
 
+#[derive(Queryable, Identifiable)]
+pub struct RecoveryCode {
+    pub id: i32,
+    pub owner: i32,
+    pub hash: String,
+}
+
+#[derive(Queryable, Identifiable, Insertable)]
+pub struct Session {
+    pub id: uuid::Uuid,
+    pub owner: i32,
+    pub tz: String,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Queryable, Identifiable)]
 pub struct Task {
     pub id: i32,
@@ -59,6 +80,127 @@ pub struct Task {
     pub link: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub note: Option<String>,
+    pub project: Option<i32>,
+    pub archived_at: Option<DateTime<Utc>>,
+    pub archived_by: Option<i32>,
+    pub is_hard_deadline: bool,
+    pub is_hard_startable: bool,
+}
+
+#[derive(Queryable, Identifiable)]
+pub struct Comment {
+    pub id: i32,
+    pub task: i32,
+    pub author: i32,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Identifiable)]
+pub struct Completion {
+    pub id: i32,
+    pub task: i32,
+    pub archived_by: Option<i32>,
+    pub archived_at: DateTime<Utc>,
+    pub reverted_by: Option<i32>,
+    pub reverted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Queryable, Identifiable)]
+pub struct Project {
+    pub id: i32,
+    pub name: String,
+    pub owner: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Insertable)]
+pub struct ProjectMember {
+    pub project: i32,
+    pub member: i32,
+    pub edit: bool,
+}
+
+#[derive(Queryable, Identifiable)]
+pub struct Link {
+    pub id: i32,
+    pub task: i32,
+    pub url: String,
+    pub label: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Identifiable)]
+pub struct Attachment {
+    pub id: i32,
+    pub task: i32,
+    pub uploader: i32,
+    pub name: String,
+    pub mime: String,
+    pub size: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Identifiable)]
+pub struct Token {
+    pub id: i32,
+    pub owner: i32,
+    pub name: String,
+    pub hash: String,
+    pub read_only: bool,
+    pub tz: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Identifiable)]
+#[primary_key(owner)]
+pub struct Totp {
+    pub owner: i32,
+    pub secret: String,
+    pub is_enabled: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Identifiable)]
+#[primary_key(owner)]
+pub struct Reminder {
+    pub owner: i32,
+    pub is_enabled: bool,
+    pub by_email: bool,
+    pub webhook_url: Option<String>,
+    pub horizon_hours: i32,
+    pub tz: String,
+    pub locale: String,
+    pub last_sent_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Queryable, Identifiable)]
+pub struct Webhook {
+    pub id: i32,
+    pub owner: i32,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<String>,
+    pub is_active: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Identifiable)]
+#[table_name = "webhook_deliveries"]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub webhook: i32,
+    pub event: String,
+    pub payload: String,
+    pub attempts: i32,
+    pub status: Option<i32>,
+    pub error: Option<String>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
 }
 
 #[derive(Queryable, Identifiable)]
@@ -79,25 +221,152 @@ pub struct User {
 pub struct AuthedUser {
     pub id: i32,
     pub tz: Tz,
+    pub session: Option<uuid::Uuid>,
+    pub scope: Scope,
+}
+
+// what the credential allows, only a cookie session managing the account itself
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Scope {
+    Session,
+    Write,
+    Read,
+}
+
+enum Credential {
+    Session(uuid::Uuid),
+    Bearer(String),
 }
 
 impl FromRequest for AuthedUser {
     type Config = ();
     type Error = Error;
-    type Future = Ready<Result<Self, Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Error>>;
 
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
         use actix_identity::RequestIdentity;
-        if let Some(identity) = req.get_identity() {
-            if let Ok(user) = serde_json::from_str::<Self>(&identity) {
-                return ok(user)
-            }
+        // the cookie only holds a session id, looked up on every request
+        let credential = match req.get_identity() {
+            Some(identity) => identity.parse::<uuid::Uuid>().ok().map(Credential::Session),
+            None => Self::bearer(req.headers()).map(Credential::Bearer),
+        };
+        if let (Some(credential), Some(pool)) = (credential, req.app_data::<web::Data<Pool>>()) {
+            let pool = pool.clone();
+            return async move {
+                web::block(move || {
+                    let conn = pool.get().unwrap();
+                    match credential {
+                        Credential::Session(sid) => Self::from_session(&sid, &conn),
+                        Credential::Bearer(bearer) => Self::from_token(&bearer, &conn),
+                    }
+                }).await.map_err(|e| errors::ServiceError::from(e).into())
+            }.boxed_local()
         }
-        err(errors::ServiceError::Unauthorized.into())
+        err(errors::ServiceError::Unauthorized.into()).boxed_local()
+    }
+}
+
+impl AuthedUser {
+    pub fn bearer(headers: &header::HeaderMap) -> Option<String> {
+        headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+    }
+    fn from_session(sid: &uuid::Uuid,
+        conn: &Conn,
+    ) -> Result<Self, errors::ServiceError> {
+        use crate::schema::sessions::dsl::{sessions, expires_at, last_seen_at};
+
+        let session = sessions
+            .find(sid)
+            .filter(expires_at.gt(Utc::now()))
+            .first::<Session>(conn).map_err(|_| errors::ServiceError::Unauthorized)?;
+        diesel::update(&session).set(last_seen_at.eq(Utc::now())).execute(conn)?;
+        Ok(Self {
+            id: session.owner,
+            tz: session.tz.parse::<Tz>().unwrap_or(Tz::UTC),
+            session: Some(session.id),
+            scope: Scope::Session,
+        })
+    }
+    // tokens look like `{id}.{secret}`, only the secret's hash is stored
+    fn from_token(bearer: &str,
+        conn: &Conn,
+    ) -> Result<Self, errors::ServiceError> {
+        use crate::schema::tokens::dsl::{tokens, last_used_at};
+
+        let mut parts = bearer.splitn(2, '.');
+        let tid = parts.next().and_then(|s| s.parse::<i32>().ok()).ok_or(errors::ServiceError::Unauthorized)?;
+        let secret = parts.next().ok_or(errors::ServiceError::Unauthorized)?;
+        let token = tokens.find(tid).first::<Token>(conn).map_err(|_| errors::ServiceError::Unauthorized)?;
+        if token.expires_at.map_or(false, |at| at < Utc::now()) || !utils::verify(&token.hash, secret)? {
+            return Err(errors::ServiceError::Unauthorized)
+        }
+        diesel::update(&token).set(last_used_at.eq(Utc::now())).execute(conn)?;
+        Ok(Self {
+            id: token.owner,
+            tz: token.tz.parse::<Tz>().unwrap_or(Tz::UTC),
+            session: None,
+            scope: if token.read_only { Scope::Read } else { Scope::Write },
+        })
+    }
+    pub fn require_write(&self) -> Result<(), errors::ServiceError> {
+        match self.scope {
+            Scope::Read => Err(errors::ServiceError::Forbidden),
+            _ => Ok(()),
+        }
+    }
+    // tokens, sessions, TOTP and credentials are out of reach of any token
+    pub fn require_session(&self) -> Result<(), errors::ServiceError> {
+        match self.scope {
+            Scope::Session => Ok(()),
+            _ => Err(errors::ServiceError::Forbidden),
+        }
+    }
+}
+
+impl Session {
+    // along with the cookie max age
+    pub fn ttl() -> Duration {
+        Duration::days(1)
+    }
+    pub fn open(user: &AuthedUser,
+        user_agent: Option<String>,
+        ip: Option<String>,
+        conn: &Conn,
+    ) -> Result<uuid::Uuid, errors::ServiceError> {
+        use crate::schema::sessions::dsl::sessions;
+
+        let now = Utc::now();
+        let session = Self {
+            id: uuid::Uuid::new_v4(),
+            owner: user.id,
+            tz: user.tz.to_string(),
+            user_agent: user_agent,
+            ip: ip,
+            expires_at: now + Self::ttl(),
+            last_seen_at: now,
+            created_at: now,
+        };
+        diesel::insert_into(sessions).values(&session).execute(conn)?;
+        Ok(session.id)
+    }
+    // on password changes, every other device has to log in again and tokens are void
+    pub fn revoke_all(user_id: i32, except: Option<uuid::Uuid>, conn: &Conn) -> Result<(), errors::ServiceError> {
+        use crate::schema::sessions::dsl::{sessions, id, owner};
+        use crate::schema::tokens::dsl::{tokens, owner as token_owner};
+
+        match except {
+            Some(keep) => diesel::delete(sessions.filter(owner.eq(&user_id)).filter(id.ne(&keep))).execute(conn)?,
+            None => diesel::delete(sessions.filter(owner.eq(&user_id))).execute(conn)?,
+        };
+        diesel::delete(tokens.filter(token_owner.eq(&user_id))).execute(conn)?;
+        Ok(())
     }
 }
 
-#[derive(Serialize, Default)]
+#[derive(Serialize, Default, Clone)]
 pub struct ResTask {
     pub id: i32,
     pub title: String,
@@ -105,14 +374,22 @@ pub struct ResTask {
     pub is_archived: bool,
     pub is_starred: bool,
     pub startable: Option<DateTime<Utc>>,
+    pub is_hard_startable: bool,
     pub deadline: Option<DateTime<Utc>>,
-    pub priority: Option<f32>,
+    pub is_hard_deadline: bool,
+    pub priority: Option<f32>, // the hard one if any, else the soft one
+    pub hard_priority: Option<f32>,
+    pub soft_priority: Option<f32>,
     pub weight: Option<f32>,
     pub link: Option<String>,
     pub schedule: Option<Schedule>,
+    pub comments: i64,
+    pub project: Option<String>,
+    pub archived_at: Option<DateTime<Utc>>,
+    pub archived_by: Option<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct Schedule {
     pub l: DateTime<Utc>,
     pub r: DateTime<Utc>,
@@ -126,9 +403,15 @@ pub struct SelTask {
     pub is_archived: bool,
     pub is_starred: bool,
     pub startable: Option<DateTime<Utc>>,
+    pub is_hard_startable: bool,
     pub deadline: Option<DateTime<Utc>>,
+    pub is_hard_deadline: bool,
     pub weight: Option<f32>,
     pub link: Option<String>,
+    pub comments: i64,
+    pub project: Option<String>,
+    pub archived_at: Option<DateTime<Utc>>,
+    pub archived_by: Option<String>,
 }
 
 pub trait Selectable {
@@ -144,9 +427,15 @@ impl Selectable for SelTask {
         tasks::is_archived,
         tasks::is_starred,
         tasks::startable,
+        tasks::is_hard_startable,
         tasks::deadline,
+        tasks::is_hard_deadline,
         tasks::weight,
         tasks::link,
+        SqlLiteral<BigInt>,
+        SqlLiteral<Nullable<Text>>,
+        tasks::archived_at,
+        SqlLiteral<Nullable<Text>>,
     );
     fn columns() -> Self::Columns {(
         tasks::id,
@@ -155,9 +444,15 @@ impl Selectable for SelTask {
         tasks::is_archived,
         tasks::is_starred,
         tasks::startable,
+        tasks::is_hard_startable,
         tasks::deadline,
+        tasks::is_hard_deadline,
         tasks::weight,
         tasks::link,
+        sql::<BigInt>("(SELECT COUNT(*) FROM comments WHERE comments.task = tasks.id)"),
+        sql::<Nullable<Text>>("(SELECT name FROM projects WHERE projects.id = tasks.project)"),
+        tasks::archived_at,
+        sql::<Nullable<Text>>("(SELECT name FROM users AS archivers WHERE archivers.id = tasks.archived_by)"),
     )}
 }
 
@@ -170,11 +465,19 @@ impl SelTask {
             is_archived: self.is_archived,
             is_starred: self.is_starred,
             startable: self.startable,
+            is_hard_startable: self.is_hard_startable,
             deadline: self.deadline,
+            is_hard_deadline: self.is_hard_deadline,
             priority: None,
+            hard_priority: None,
+            soft_priority: None,
             weight: self.weight,
             link: self.link,
             schedule: None,
+            comments: self.comments,
+            project: self.project,
+            archived_at: self.archived_at,
+            archived_by: self.archived_by,
         }
     }
 }
@@ -217,6 +520,15 @@ impl Not for LR {
     }
 }
 
+impl LR {
+    fn column(&self) -> &'static str {
+        match self {
+            Self::Leaf => "source",
+            Self::Root => "target",
+        }
+    }
+}
+
 impl Arrow {
     pub fn trace_to(&self, lr: LR) -> i32 {
         match lr {
@@ -232,8 +544,6 @@ pub struct Tid {
     pub id: i32,
 }
 
-pub type Path = Vec<i32>;
-
 impl From<i32> for Tid {
     fn from(id: i32) -> Self {
         Self { id: id }
@@ -244,50 +554,6 @@ impl Tid {
     pub fn is(&self, lr: LR, arrows: &Arrows) -> bool {
         arrows.arrows.iter().all(|arw| arw.trace_to(!lr) != self.id)
     }
-    pub fn paths_to(&self, lr: LR, arrows: &Arrows) -> Vec<Path> {
-        let map = arrows.map_to(lr);
-        let mut results: Vec<Path> = Vec::new();
-        let mut remains: Vec<i32> = Vec::new();
-        let mut re_map: HashMap<i32, Vec<i32>> = HashMap::new();
-        let mut cursor = self.id;
-        let mut path: Vec<i32> = Vec::new();
-        'main: loop {
-            if path.contains(&cursor) { // got cycle instead of path
-                results.clear();
-                break
-            }
-            path.push(cursor);
-            if let Some(destinations) = map.get(&cursor) {
-                let mut destinations = destinations.clone();
-                if let Some(dest) = destinations.pop() {
-                    remains.push(cursor);
-                    re_map.insert(cursor, destinations);
-                    cursor = dest;
-                    continue
-                }
-            }
-            results.push(Path::from(path.clone()));
-            while let Some(rem) = remains.pop() {
-                while cursor != rem {
-                    cursor = path.pop().unwrap();
-                }
-                path.push(cursor);
-                if let Some(dest) = re_map.get_mut(&cursor).unwrap().pop() {
-                    remains.push(cursor);
-                    cursor = dest;
-                    continue 'main
-                }
-            }
-            break
-        }
-        results
-    }
-    pub fn nodes_to(&self, lr: LR, arrows: &Arrows) -> Vec<i32> {
-        let mut nodes = self.paths_to(lr, arrows).into_iter().flatten().collect::<Vec<i32>>();
-        nodes.sort();
-        nodes.dedup();
-        nodes
-    }
 }
 
 impl Arrows {
@@ -305,12 +571,6 @@ impl Arrows {
             .into()
         )
     }
-    pub fn paths(&self) -> Vec<Path> {
-        self.list(LR::Leaf).iter().flat_map(|leaf| Tid::from(*leaf).paths_to(LR::Root, &self)).collect()
-    }
-    pub fn list(&self, lr: LR) -> Vec<i32> {
-        self.nodes().into_iter().filter(|id| Tid::from(*id).is(lr, &self)).collect()
-    }
     pub fn nodes(&self) -> Vec<i32> {
         let mut ids = Vec::new();
         for arw in &self.arrows {
@@ -321,15 +581,6 @@ impl Arrows {
         ids.dedup();
         ids
     }
-    pub fn has_cycle(&self) -> bool {
-        if self.arrows.is_empty() {
-            return false
-        }
-        if self.list(LR::Leaf).is_empty() || self.list(LR::Root).is_empty() {
-            return true
-        }
-        self.list(LR::Leaf).iter().any(|leaf| Tid::from(*leaf).paths_to(LR::Root, &self).is_empty())
-    }
 }
 
 #[derive(Debug, Default, PartialEq, PartialOrd)]
@@ -408,15 +659,66 @@ impl AuthedUser {
                 if lower < dt && dt < upper {
                     return Ok(dt.with_timezone(&Utc))
                 }
-                return Err(errors::ServiceError::BadRequest("some dates are out of range.".into()))
+                return Err(errors::ServiceError::bad_request(errors::Code::OutOfRange, "some dates are out of range."))
             }
         }
-        Err(errors::ServiceError::BadRequest("failed to interpret datetime.".into()))
+        Err(errors::ServiceError::bad_request(errors::Code::InvalidDatetime, "failed to interpret datetime."))
     }
     pub fn localize(&self, dt: &DateTime<Utc>) -> String {
         let local = dt.with_timezone(&self.tz).naive_local();
         local.format("%Y/%m/%dT%H:%M").to_string()
     }
+    // tasks open to the user through permissions on the assignee, or through membership of the project
+    pub fn permits(&self, edit: bool) -> SqlLiteral<Bool> {
+        sql::<Bool>(&self.permits_clause(edit))
+    }
+    fn permits_clause(&self, edit: bool) -> String {
+        format!("\
+            (EXISTS (SELECT 1 FROM permissions \
+                WHERE permissions.subject = {id} AND permissions.object = tasks.assign{pe}) \
+            OR EXISTS (SELECT 1 FROM project_members \
+                WHERE project_members.member = {id} AND project_members.project = tasks.project{me}))",
+            id = self.id,
+            pe = if edit { " AND permissions.edit" } else { "" },
+            me = if edit { " AND project_members.edit" } else { "" },
+        )
+    }
+    // traverse only permitted tasks, in DB instead of loading all arrows
+    pub fn nodes_to(&self,
+        lr: LR,
+        ids: &Vec<i32>,
+        conn: &Conn,
+    ) -> Result<Vec<i32>, errors::ServiceError> {
+        use diesel::sql_types::{Array, Integer};
+
+        let query = format!("\
+            WITH RECURSIVE permitted AS (
+                SELECT tasks.id FROM tasks WHERE {permits}
+            ), nodes(id) AS (
+                SELECT id FROM permitted WHERE id = ANY($1)
+                UNION
+                SELECT arrows.{next} FROM arrows
+                INNER JOIN nodes ON arrows.{prev} = nodes.id
+                INNER JOIN permitted ON permitted.id = arrows.{next}
+            )
+            SELECT id FROM nodes ORDER BY id
+            ",
+            next = lr.column(),
+            prev = (!lr).column(),
+            permits = self.permits_clause(false),
+        );
+        Ok(diesel::sql_query(query)
+            .bind::<Array<Integer>, _>(ids)
+            .load::<Node>(conn)?
+            .into_iter().map(|node| node.id).collect()
+        )
+    }
+}
+
+#[derive(QueryableByName)]
+struct Node {
+    #[sql_type = "diesel::sql_types::Integer"]
+    id: i32,
 }
 
 impl Selectable for Allocation {
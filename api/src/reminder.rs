@@ -0,0 +1,303 @@
+use actix_web::web;
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use serde::Serialize;
+
+use crate::errors;
+use crate::handlers::app::home;
+use crate::mail;
+use crate::models::{self, Selectable};
+use crate::webhook;
+
+// wakes up every REMINDER_INTERVAL_SECONDS and sends each opted-in user at most one digest a day
+pub fn spawn(pool: models::Pool, mailer: web::Data<mail::Mailer>) {
+    let interval = std::env::var("REMINDER_INTERVAL_SECONDS").ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(300);
+    std::thread::spawn(move || loop {
+        if let Err(err) = tick(&pool, &mailer, Utc::now()) {
+            println!("Reminder Error:\n{:#?}", err);
+        }
+        std::thread::sleep(std::time::Duration::from_secs(interval));
+    });
+}
+
+fn tick(pool: &models::Pool, mailer: &mail::Mailer, now: DateTime<Utc>) -> Result<(), errors::ServiceError> {
+    use crate::schema::reminders::dsl::{reminders, is_enabled};
+
+    let conn = pool.get().map_err(|err| {
+        println!("Reminder Pool Error:\n{:#?}", err);
+        errors::ServiceError::InternalServerError
+    })?;
+    for reminder in reminders.filter(is_enabled).load::<models::Reminder>(&conn)? {
+        // one broken destination should not stop the others
+        if let Err(err) = remind(&reminder, mailer, now, &conn) {
+            println!("Reminder Error for user {}:\n{:#?}", reminder.owner, err);
+        }
+    }
+    Ok(())
+}
+
+fn remind(
+    reminder: &models::Reminder,
+    mailer: &mail::Mailer,
+    now: DateTime<Utc>,
+    conn: &models::Conn,
+) -> Result<(), errors::ServiceError> {
+    use crate::schema::allocations::dsl::{allocations, owner};
+    use crate::schema::reminders::dsl::{reminders, last_sent_at};
+    use crate::schema::tasks::dsl::{tasks, assign, is_archived};
+    use crate::schema::users::dsl::users;
+
+    let tz = reminder.tz.parse::<Tz>().unwrap_or(Tz::UTC);
+    if reminder.last_sent_at.map(|at| at.with_timezone(&tz).date()) == Some(now.with_timezone(&tz).date()) {
+        return Ok(())
+    }
+    let _allocations = allocations
+        .filter(owner.eq(&reminder.owner))
+        .select(models::Allocation::columns())
+        .load::<models::Allocation>(conn)?;
+    if !is_awake(&_allocations, tz, now) {
+        return Ok(())
+    }
+    let mut _tasks = tasks
+        .filter(assign.eq(&reminder.owner))
+        .filter(is_archived.eq(false))
+        .inner_join(users)
+        .select(models::SelTask::columns())
+        .load::<models::SelTask>(conn)?
+        .into_iter().map(|t| t.to_res()).collect::<Vec<models::ResTask>>();
+    let arrows = models::Arrows::among(&_tasks, conn)?;
+    let user = models::AuthedUser {
+        id: reminder.owner,
+        tz: tz,
+        session: None,
+        scope: models::Scope::Read,
+    };
+    home::sort(&mut _tasks, arrows, &user, conn)?;
+    let digest = Digest::new(&_tasks, now, Duration::hours(reminder.horizon_hours as i64));
+    if digest.is_empty() {
+        return Ok(())
+    }
+    let mut results = Vec::new();
+    if reminder.by_email {
+        let email = users.find(reminder.owner).first::<models::User>(conn)?.email;
+        results.push(("email", send_email(reminder, &digest, &email, mailer, now)));
+    }
+    if let Some(url) = &reminder.webhook_url {
+        results.push(("webhook", webhook::post(url, &digest)));
+    }
+    settle(reminder.owner, results)?;
+    diesel::update(reminders.find(reminder.owner)).set(last_sent_at.eq(now)).execute(conn)?;
+    Ok(())
+}
+
+fn send_email(
+    reminder: &models::Reminder,
+    digest: &Digest,
+    email: &str,
+    mailer: &mail::Mailer,
+    now: DateTime<Utc>,
+) -> Result<(), errors::ServiceError> {
+    let tz = reminder.tz.parse::<Tz>().unwrap_or(Tz::UTC);
+    let template = mail::Template {
+        name: "deadline_reminder",
+        locale: reminder.locale.clone(),
+    };
+    let vars = vec![
+        ("count", digest.len().to_string()),
+        ("until", format(now + Duration::hours(reminder.horizon_hours as i64), tz)),
+        ("tz", reminder.tz.clone()),
+        ("tasks", digest.lines(tz)),
+    ];
+    mailer.send(&template, email, &vars)
+}
+
+// sent once any channel delivered, not to repeat it all day through the ones that worked
+fn settle(owner: i32, results: Vec<(&str, Result<(), errors::ServiceError>)>) -> Result<(), errors::ServiceError> {
+    let mut sent = false;
+    let mut last_err = None;
+    for (channel, result) in results {
+        match result {
+            Ok(()) => sent = true,
+            Err(err) => {
+                println!("Reminder {} Error for user {}:\n{:#?}", channel, owner, err);
+                last_err = Some(err);
+            },
+        }
+    }
+    match last_err {
+        Some(err) if !sent => Err(err),
+        _ => Ok(()),
+    }
+}
+
+// quiet outside allocations, including the one opened yesterday and still open; always awake without any
+fn is_awake(allocations: &Vec<models::Allocation>, tz: Tz, now: DateTime<Utc>) -> bool {
+    if allocations.is_empty() {
+        return true
+    }
+    let today = now.with_timezone(&tz).date();
+    allocations.iter().any(|alc| {
+        [today.pred(), today].iter().filter_map(|date| date.and_time(alc.open)).any(|open| {
+            open <= now && now < open + Duration::hours(alc.hours as i64)
+        })
+    })
+}
+
+fn format(dt: DateTime<Utc>, tz: Tz) -> String {
+    dt.with_timezone(&tz).format("%Y/%m/%d %a %H:%M").to_string()
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct Digest {
+    event: &'static str,
+    overdue: Vec<Item>,
+    due_soon: Vec<Item>,
+    projected_late: Vec<Item>, // `priority` positive: not to finish by the deadline as scheduled
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct Item {
+    id: i32,
+    title: String,
+    deadline: Option<DateTime<Utc>>,
+    priority: Option<f32>,
+}
+
+impl Digest {
+    fn new(tasks: &Vec<models::ResTask>, now: DateTime<Utc>, horizon: Duration) -> Self {
+        let mut digest = Self {
+            event: "deadline_reminder",
+            overdue: Vec::new(),
+            due_soon: Vec::new(),
+            projected_late: Vec::new(),
+        };
+        for t in tasks {
+            let item = Item {
+                id: t.id,
+                title: t.title.clone(),
+                deadline: t.deadline,
+                priority: t.priority,
+            };
+            match t.deadline {
+                Some(deadline) if deadline < now => digest.overdue.push(item),
+                Some(deadline) if deadline < now + horizon => digest.due_soon.push(item),
+                _ if t.priority.map_or(false, |p| 0.0 < p) => digest.projected_late.push(item),
+                _ => (),
+            }
+        }
+        digest
+    }
+    fn len(&self) -> usize {
+        self.overdue.len() + self.due_soon.len() + self.projected_late.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn lines(&self, tz: Tz) -> String {
+        let mut lines = Vec::new();
+        for (label, items) in &[
+            ("OVERDUE", &self.overdue),
+            ("DUE SOON", &self.due_soon),
+            ("PROJECTED LATE", &self.projected_late),
+        ] {
+            for item in items.iter() {
+                lines.push(format!("[{}] #{} {}{}",
+                    label,
+                    item.id,
+                    item.title,
+                    item.deadline.map(|dt| format!(" -- {}", format(dt, tz))).unwrap_or_default(),
+                ))
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveTime, TimeZone};
+
+    fn task(id: i32, deadline: Option<DateTime<Utc>>, priority: Option<f32>) -> models::ResTask {
+        models::ResTask {
+            id: id,
+            title: format!("task {}", id),
+            deadline: deadline,
+            priority: priority,
+            soft_priority: priority,
+            ..Default::default()
+        }
+    }
+    struct Failing;
+
+    impl mail::Transport for Failing {
+        fn send(&self, _: &mail::Mail) -> Result<(), errors::ServiceError> {
+            Err(errors::ServiceError::InternalServerError)
+        }
+    }
+    #[test]
+    fn t_settle() {
+        let now = Utc.ymd(2021, 2, 10).and_hms(9, 0, 0);
+        let reminder = models::Reminder {
+            owner: 1,
+            is_enabled: true,
+            by_email: true,
+            webhook_url: Some(String::from("https://93.184.216.34/hook")),
+            horizon_hours: 24,
+            tz: String::from("Asia/Tokyo"),
+            locale: String::from("en"),
+            last_sent_at: None,
+        };
+        let mailer = mail::Mailer::new(
+            Box::new(Failing),
+            mail::Sender {
+                addr: String::from("noreply@example.com"),
+                name: String::from("app"),
+            },
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("templates"),
+        );
+        let digest = Digest::new(&vec![task(1, Some(now - Duration::hours(1)), None)], now, Duration::hours(24));
+        let email = || send_email(&reminder, &digest, "someone@example.com", &mailer, now);
+        assert!(email().is_err());
+        // the webhook went out, so it is not to be sent again today
+        assert!(settle(1, vec![("email", email()), ("webhook", Ok(()))]).is_ok());
+        assert!(settle(1, vec![("webhook", Ok(())), ("email", email())]).is_ok());
+        assert!(settle(1, vec![("email", email()), ("webhook", Err(errors::ServiceError::InternalServerError))]).is_err());
+        assert!(settle(1, vec![]).is_ok());
+    }
+    #[test]
+    fn t_digest() {
+        let now = Utc.ymd(2021, 2, 10).and_hms(9, 0, 0);
+        let tasks = vec![
+            task(1, Some(now - Duration::hours(1)), Some(3.0)),
+            task(2, Some(now + Duration::hours(3)), Some(-1.0)),
+            task(3, Some(now + Duration::days(3)), Some(2.0)),
+            task(4, Some(now + Duration::days(3)), Some(-2.0)),
+            task(5, None, None),
+        ];
+        let digest = Digest::new(&tasks, now, Duration::hours(24));
+        let ids = |items: &Vec<Item>| items.iter().map(|i| i.id).collect::<Vec<i32>>();
+        assert_eq!(ids(&digest.overdue), vec![1]);
+        assert_eq!(ids(&digest.due_soon), vec![2]);
+        assert_eq!(ids(&digest.projected_late), vec![3]);
+        assert_eq!(digest.lines(Tz::UTC).lines().next(), Some("[OVERDUE] #1 task 1 -- 2021/02/10 Wed 08:00"));
+        assert!(Digest::new(&vec![task(4, Some(now + Duration::days(3)), None)], now, Duration::hours(24)).is_empty());
+    }
+    #[test]
+    fn t_awake() {
+        let tz = Tz::Asia__Tokyo;
+        let allocations = vec![
+            models::Allocation { owner: 1, open: NaiveTime::from_hms(9, 0, 0), hours: 8 },
+            models::Allocation { owner: 1, open: NaiveTime::from_hms(22, 0, 0), hours: 4 },
+        ];
+        let at = |h, m| tz.ymd(2021, 2, 10).and_hms(h, m, 0).with_timezone(&Utc);
+        assert!(is_awake(&allocations, tz, at(9, 0)));
+        assert!(!is_awake(&allocations, tz, at(17, 0)));
+        assert!(is_awake(&allocations, tz, at(1, 30))); // opened yesterday
+        assert!(!is_awake(&allocations, tz, at(2, 0)));
+        assert!(is_awake(&vec![], tz, at(2, 0)));
+    }
+}
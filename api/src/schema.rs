@@ -14,6 +14,39 @@ table! {
     }
 }
 
+table! {
+    attachments (id) {
+        id -> Int4,
+        task -> Int4,
+        uploader -> Int4,
+        name -> Varchar,
+        mime -> Varchar,
+        size -> Int4,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    comments (id) {
+        id -> Int4,
+        task -> Int4,
+        author -> Int4,
+        body -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    completions (id) {
+        id -> Int4,
+        task -> Int4,
+        archived_by -> Nullable<Int4>,
+        archived_at -> Timestamptz,
+        reverted_by -> Nullable<Int4>,
+        reverted_at -> Nullable<Timestamptz>,
+    }
+}
+
 table! {
     invitations (id) {
         id -> Uuid,
@@ -21,6 +54,17 @@ table! {
         expires_at -> Timestamptz,
         forgot_pw -> Bool,
         tz -> Varchar,
+        locale -> Varchar,
+    }
+}
+
+table! {
+    links (id) {
+        id -> Int4,
+        task -> Int4,
+        url -> Varchar,
+        label -> Nullable<Varchar>,
+        created_at -> Timestamptz,
     }
 }
 
@@ -32,6 +76,57 @@ table! {
     }
 }
 
+table! {
+    project_members (project, member) {
+        project -> Int4,
+        member -> Int4,
+        edit -> Bool,
+    }
+}
+
+table! {
+    projects (id) {
+        id -> Int4,
+        name -> Varchar,
+        owner -> Int4,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    recovery_codes (id) {
+        id -> Int4,
+        owner -> Int4,
+        hash -> Varchar,
+    }
+}
+
+table! {
+    reminders (owner) {
+        owner -> Int4,
+        is_enabled -> Bool,
+        by_email -> Bool,
+        webhook_url -> Nullable<Varchar>,
+        horizon_hours -> Int4,
+        tz -> Varchar,
+        locale -> Varchar,
+        last_sent_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    sessions (id) {
+        id -> Uuid,
+        owner -> Int4,
+        tz -> Varchar,
+        user_agent -> Nullable<Varchar>,
+        ip -> Nullable<Varchar>,
+        expires_at -> Timestamptz,
+        last_seen_at -> Timestamptz,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     tasks (id) {
         id -> Int4,
@@ -45,6 +140,35 @@ table! {
         link -> Nullable<Varchar>,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
+        note -> Nullable<Text>,
+        project -> Nullable<Int4>,
+        archived_at -> Nullable<Timestamptz>,
+        archived_by -> Nullable<Int4>,
+        is_hard_deadline -> Bool,
+        is_hard_startable -> Bool,
+    }
+}
+
+table! {
+    tokens (id) {
+        id -> Int4,
+        owner -> Int4,
+        name -> Varchar,
+        hash -> Varchar,
+        read_only -> Bool,
+        tz -> Varchar,
+        expires_at -> Nullable<Timestamptz>,
+        last_used_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    totps (owner) {
+        owner -> Int4,
+        secret -> Varchar,
+        is_enabled -> Bool,
+        created_at -> Timestamptz,
     }
 }
 
@@ -60,14 +184,71 @@ table! {
     }
 }
 
+table! {
+    webhook_deliveries (id) {
+        id -> Int4,
+        webhook -> Int4,
+        event -> Varchar,
+        payload -> Text,
+        attempts -> Int4,
+        status -> Nullable<Int4>,
+        error -> Nullable<Varchar>,
+        next_attempt_at -> Nullable<Timestamptz>,
+        delivered_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    webhooks (id) {
+        id -> Int4,
+        owner -> Int4,
+        url -> Varchar,
+        secret -> Varchar,
+        events -> Array<Varchar>,
+        is_active -> Bool,
+        created_at -> Timestamptz,
+    }
+}
+
 joinable!(allocations -> users (owner));
+joinable!(attachments -> tasks (task));
+joinable!(attachments -> users (uploader));
+joinable!(comments -> tasks (task));
+joinable!(comments -> users (author));
+joinable!(completions -> tasks (task));
+joinable!(links -> tasks (task));
+joinable!(project_members -> projects (project));
+joinable!(project_members -> users (member));
+joinable!(projects -> users (owner));
+joinable!(recovery_codes -> users (owner));
+joinable!(reminders -> users (owner));
+joinable!(sessions -> users (owner));
+joinable!(tasks -> projects (project));
 joinable!(tasks -> users (assign));
+joinable!(tokens -> users (owner));
+joinable!(totps -> users (owner));
+joinable!(webhook_deliveries -> webhooks (webhook));
+joinable!(webhooks -> users (owner));
 
 allow_tables_to_appear_in_same_query!(
     allocations,
     arrows,
+    attachments,
+    comments,
+    completions,
     invitations,
+    links,
     permissions,
+    project_members,
+    projects,
+    recovery_codes,
+    reminders,
+    sessions,
     tasks,
+    tokens,
+    totps,
     users,
+    webhook_deliveries,
+    webhooks,
 );
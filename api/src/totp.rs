@@ -0,0 +1,125 @@
+use hmac::{Hmac, Mac, NewMac};
+use rand::Rng;
+use sha1::Sha1;
+
+// RFC 6238 with the defaults authenticator apps expect: SHA-1, 6 digits, 30 seconds
+const DIGITS: u32 = 6;
+const STEP: i64 = 30;
+// steps of clock drift to tolerate on each side
+const SKEW: i64 = 1;
+
+const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+pub fn new_secret() -> String {
+    encode(&rand::thread_rng().gen::<[u8; 20]>())
+}
+
+pub fn uri(secret: &str, account: &str) -> String {
+    let issuer = std::env::var("APP_NAME").unwrap_or_else(|_| String::from("tasks"));
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = issuer,
+        account = account,
+        secret = secret,
+        digits = DIGITS,
+        period = STEP,
+    )
+}
+
+// `now` in unix seconds, given by the caller to keep this deterministic
+pub fn verify(secret: &str, code: &str, now: i64) -> bool {
+    let key = match decode(secret) {
+        Some(key) => key,
+        None => return false,
+    };
+    let code = code.trim();
+    (-SKEW..=SKEW).any(|skew| {
+        let counter = now / STEP + skew;
+        0 <= counter && format!("{:0width$}", hotp(&key, counter as u64), width = DIGITS as usize) == code
+    })
+}
+
+fn hotp(key: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_varkey(key).unwrap(); // any key length is accepted
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let binary = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]) & 0x7fff_ffff;
+    binary % 10u32.pow(DIGITS)
+}
+
+// RFC 4648 base32 without padding
+fn encode(bytes: &[u8]) -> String {
+    let mut res = String::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = buffer << 8 | *byte as u32;
+        bits += 8;
+        while 5 <= bits {
+            bits -= 5;
+            res.push(ALPHABET[(buffer >> bits & 0x1f) as usize] as char);
+        }
+    }
+    if 0 < bits {
+        res.push(ALPHABET[(buffer << (5 - bits) & 0x1f) as usize] as char);
+    }
+    res
+}
+
+fn decode(s: &str) -> Option<Vec<u8>> {
+    let mut res = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in s.trim_end_matches('=').chars().filter(|c| !c.is_whitespace()) {
+        let value = ALPHABET.iter().position(|a| *a as char == c.to_ascii_uppercase())? as u32;
+        buffer = buffer << 5 | value;
+        bits += 5;
+        if 8 <= bits {
+            bits -= 8;
+            res.push((buffer >> bits & 0xff) as u8);
+        }
+    }
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the RFC 6238 appendix B seed, "12345678901234567890"
+    fn secret() -> String {
+        encode(b"12345678901234567890")
+    }
+    #[test]
+    fn t_base32() {
+        assert_eq!(encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(decode("MZXW6YTBOI"), Some(b"foobar".to_vec()));
+        assert_eq!(decode("mzxw6ytboi======"), Some(b"foobar".to_vec()));
+        assert_eq!(decode("MZXW6YTB0I"), None);
+        assert_eq!(secret(), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+    }
+    #[test]
+    fn t_hotp() {
+        // RFC 4226 appendix D
+        let key = b"12345678901234567890";
+        let expected = [755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871, 520489];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(hotp(key, counter as u64), *code);
+        }
+    }
+    #[test]
+    fn t_verify() {
+        // RFC 6238 appendix B, last 6 digits
+        assert!(verify(&secret(), "287082", 59));
+        assert!(verify(&secret(), "081804", 1111111109));
+        assert!(verify(&secret(), "050471", 1111111111));
+        assert!(verify(&secret(), "005924", 1234567890));
+        assert!(verify(&secret(), "279037", 2000000000));
+        // within a step of drift
+        assert!(verify(&secret(), "287082", 59 + STEP));
+        assert!(!verify(&secret(), "287082", 59 + STEP * 2));
+        assert!(!verify(&secret(), "000000", 59));
+        assert!(!verify("not base32!", "287082", 59));
+    }
+}
@@ -0,0 +1,420 @@
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use hmac::{Hmac, Mac, NewMac};
+use reqwest::{blocking::Client, header};
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::HashSet;
+use std::net::IpAddr;
+
+use crate::errors;
+use crate::models::{self, Selectable};
+use crate::schema::webhook_deliveries;
+
+// attempts in total, waiting BACKOFF seconds after the first failure and doubling since
+const MAX_ATTEMPTS: i32 = 6;
+const BACKOFF: i64 = 30;
+
+// arrows only ever get added: text rejects rewiring existing nodes and archiving keeps them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    TaskCreated,
+    TaskUpdated,
+    TaskArchived,
+    TaskReverted,
+    TaskStarred,
+    TaskUnstarred,
+    ArrowCreated,
+    CommentCreated,
+}
+
+impl Event {
+    pub fn all() -> Vec<Self> {
+        vec![
+            Self::TaskCreated,
+            Self::TaskUpdated,
+            Self::TaskArchived,
+            Self::TaskReverted,
+            Self::TaskStarred,
+            Self::TaskUnstarred,
+            Self::ArrowCreated,
+            Self::CommentCreated,
+        ]
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::TaskCreated    => "task.created",
+            Self::TaskUpdated    => "task.updated",
+            Self::TaskArchived   => "task.archived",
+            Self::TaskReverted   => "task.reverted",
+            Self::TaskStarred    => "task.starred",
+            Self::TaskUnstarred  => "task.unstarred",
+            Self::ArrowCreated   => "arrow.created",
+            Self::CommentCreated => "comment.created",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    event: &'static str,
+    actor: &'a str,
+    tasks: Vec<&'a models::ResTask>,
+    arrows: Vec<&'a models::Arrow>,
+    emitted_at: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[table_name = "webhook_deliveries"]
+struct NewDelivery {
+    webhook: i32,
+    event: String,
+    payload: String,
+    next_attempt_at: Option<DateTime<Utc>>,
+}
+
+// queues deliveries to the actor and to the assignees, each seeing only their own tasks
+pub fn emit(
+    event: Event,
+    user: &models::AuthedUser,
+    ids: &Vec<i32>,
+    arrows: &Vec<models::Arrow>,
+    conn: &models::Conn,
+) -> Result<(), errors::ServiceError> {
+    use crate::schema::tasks::dsl::{tasks, id, assign};
+    use crate::schema::users::dsl::{users, name};
+    use crate::schema::webhooks::dsl::{webhooks, owner, is_active};
+
+    if ids.is_empty() && arrows.is_empty() {
+        return Ok(())
+    }
+    let mut involved = ids.clone();
+    involved.extend(arrows.iter().flat_map(|arw| vec![arw.source, arw.target]));
+    let _tasks = tasks
+        .filter(id.eq_any(&involved))
+        .inner_join(users)
+        .select((models::SelTask::columns(), assign))
+        .load::<(models::SelTask, i32)>(conn)?
+        .into_iter().map(|(t, a)| (t.to_res(), a)).collect::<Vec<(models::ResTask, i32)>>();
+    let mut recipients = _tasks.iter().map(|(_, a)| *a).collect::<HashSet<i32>>();
+    recipients.insert(user.id);
+    let hooks = webhooks
+        .filter(owner.eq_any(recipients.into_iter().collect::<Vec<i32>>()))
+        .filter(is_active)
+        .load::<models::Webhook>(conn)?;
+    if hooks.is_empty() {
+        return Ok(())
+    }
+    let actor = users.find(user.id).select(name).first::<String>(conn)?;
+    let now = Utc::now();
+    let mut news = Vec::new();
+    for hook in hooks.iter().filter(|h| h.events.is_empty() || h.events.iter().any(|e| e == event.as_str())) {
+        let visibles = _tasks.iter()
+            .filter(|(_, a)| hook.owner == user.id || *a == hook.owner)
+            .map(|(t, _)| t.id)
+            .collect::<HashSet<i32>>();
+        let payload = Payload {
+            event: event.as_str(),
+            actor: &actor,
+            tasks: _tasks.iter().map(|(t, _)| t).filter(|t| ids.contains(&t.id) && visibles.contains(&t.id)).collect(),
+            arrows: arrows.iter().filter(|arw| visibles.contains(&arw.source) || visibles.contains(&arw.target)).collect(),
+            emitted_at: now,
+        };
+        if payload.tasks.is_empty() && payload.arrows.is_empty() {
+            continue
+        }
+        news.push(NewDelivery {
+            webhook: hook.id,
+            event: event.as_str().into(),
+            payload: serde_json::to_string(&payload).map_err(|err| {
+                println!("Webhook Payload Error:\n{:#?}", err);
+                errors::ServiceError::InternalServerError
+            })?,
+            next_attempt_at: Some(now),
+        })
+    }
+    diesel::insert_into(webhook_deliveries::table).values(&news).execute(conn)?;
+    Ok(())
+}
+
+// wakes up every WEBHOOK_INTERVAL_SECONDS to deliver what is due
+pub fn spawn(pool: models::Pool) {
+    let interval = std::env::var("WEBHOOK_INTERVAL_SECONDS").ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(5);
+    std::thread::spawn(move || loop {
+        if let Err(err) = flush(&pool, Utc::now()) {
+            println!("Webhook Error:\n{:#?}", err);
+        }
+        std::thread::sleep(std::time::Duration::from_secs(interval));
+    });
+}
+
+fn flush(pool: &models::Pool, now: DateTime<Utc>) -> Result<(), errors::ServiceError> {
+    use crate::schema::webhook_deliveries::dsl::*;
+    use crate::schema::webhooks::dsl::webhooks;
+
+    let conn = pool.get().map_err(|err| {
+        println!("Webhook Pool Error:\n{:#?}", err);
+        errors::ServiceError::InternalServerError
+    })?;
+    let client = client()?;
+    let due = webhook_deliveries
+        .inner_join(webhooks)
+        .filter(next_attempt_at.le(now))
+        .order(id)
+        .limit(100)
+        .load::<(models::WebhookDelivery, models::Webhook)>(&conn)?;
+    for (delivery, hook) in due {
+        let tried = delivery.attempts + 1;
+        // the URL is checked again in case its host now resolves to an internal address
+        let sent = check_url(&hook.url).map_err(|err| (None, err)).and_then(|_| deliver(&client, &hook, &delivery));
+        match sent {
+            Ok(code) => diesel::update(&delivery).set((
+                attempts.eq(tried),
+                status.eq(Some(code)),
+                error.eq(None::<String>),
+                next_attempt_at.eq(None::<DateTime<Utc>>),
+                delivered_at.eq(Some(now)),
+            )).execute(&conn)?,
+            Err((code, err)) => diesel::update(&delivery).set((
+                attempts.eq(tried),
+                status.eq(code),
+                error.eq(Some(err)),
+                next_attempt_at.eq(retry_at(tried, now)),
+            )).execute(&conn)?,
+        };
+    }
+    Ok(())
+}
+
+fn retry_at(attempts: i32, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if MAX_ATTEMPTS <= attempts {
+        return None
+    }
+    Some(now + Duration::seconds(BACKOFF << (attempts - 1)))
+}
+
+// the status code, or along with what went wrong
+fn deliver(
+    client: &Client,
+    hook: &models::Webhook,
+    delivery: &models::WebhookDelivery,
+) -> Result<i32, (Option<i32>, String)> {
+    let resp = client.post(&hook.url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header("X-Webhook-Event", delivery.event.as_str())
+        .header("X-Webhook-Delivery", delivery.id.to_string())
+        .header("X-Webhook-Signature", format!("sha256={}", sign(&hook.secret, &delivery.payload)))
+        .body(delivery.payload.clone())
+        .send()
+        .map_err(|err| (None, err.to_string()))?;
+    let code = resp.status().as_u16() as i32;
+    if resp.status().is_success() {
+        Ok(code)
+    } else {
+        Err((Some(code), format!("unexpected status {}", resp.status())))
+    }
+}
+
+// hex HMAC-SHA256 of the raw body, for receivers to verify with the secret
+fn sign(secret: &str, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).unwrap(); // any key length is accepted
+    mac.update(payload.as_bytes());
+    mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// not following redirects nor waiting long on slow receivers
+fn client() -> Result<Client, errors::ServiceError> {
+    Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .map_err(|err| {
+            println!("Webhook Client Error:\n{:#?}", err);
+            errors::ServiceError::InternalServerError
+        })
+}
+
+// only http(s) to public addresses, checked on save and again before each request
+// as the host may resolve elsewhere by then.
+// reqwest still resolves on its own when connecting, so a host answering a public address here
+// and an internal one a moment later slips through; pinning the checked address would break TLS for https
+pub fn check_url(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|err| format!("invalid webhook URL: {}.", err))?;
+    if !(parsed.scheme() == "https" || parsed.scheme() == "http") {
+        return Err(String::from("webhook URL must be http(s)."))
+    }
+    let addrs = parsed.socket_addrs(|| None).map_err(|err| format!("webhook URL host not resolved: {}.", err))?;
+    if addrs.is_empty() {
+        return Err(String::from("webhook URL host not resolved."))
+    }
+    match addrs.iter().find(|addr| is_internal(&addr.ip())) {
+        Some(addr) => Err(format!("webhook URL must not point to an internal address: {}.", addr.ip())),
+        None => Ok(()),
+    }
+}
+
+// loopback, private, link-local and other non-global ranges
+fn is_internal(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let o = v4.octets();
+            v4.is_loopback() || v4.is_private() || v4.is_link_local() || v4.is_unspecified()
+            || v4.is_broadcast() || v4.is_multicast() || v4.is_documentation()
+            || o[0] == 0
+            || (o[0] == 100 && (o[1] & 0xc0) == 64) // shared address space
+            || (o[0] == 192 && o[1] == 0 && o[2] == 0) // protocol assignments
+            || (o[0] == 198 && (o[1] & 0xfe) == 18) // benchmarking
+            || 240 <= o[0] // reserved
+        },
+        IpAddr::V6(v6) => {
+            let s = v6.segments();
+            if let Some(v4) = v6.to_ipv4() {
+                if s[5] == 0xffff || s[..6].iter().all(|x| *x == 0) {
+                    return is_internal(&IpAddr::V4(v4))
+                }
+            }
+            v6.is_loopback() || v6.is_unspecified() || v6.is_multicast()
+            || (s[0] & 0xfe00) == 0xfc00 // unique local
+            || (s[0] & 0xffc0) == 0xfe80 // link-local
+            || (s[0] & 0xffc0) == 0xfec0 // site-local
+            || (s[0] == 0x2001 && s[1] == 0x0db8) // documentation
+            || (s[0] == 0x0064 && s[1] == 0xff9b) // NAT64
+        },
+    }
+}
+
+// POSTs a JSON payload once, unsigned
+pub fn post<T: Serialize>(url: &str, payload: &T) -> Result<(), errors::ServiceError> {
+    if let Err(err) = check_url(url) {
+        println!("Webhook URL Rejected {}:\n{}", url, err);
+        return Err(errors::ServiceError::InternalServerError)
+    }
+    match client()?.post(url).json(payload).send() {
+        Ok(resp) if resp.status().is_success() => Ok(()),
+        Ok(resp) => {
+            println!("Webhook Response from {}:\n{:#?}", url, resp.status());
+            Err(errors::ServiceError::InternalServerError)
+        },
+        Err(req_err) => {
+            println!("Webhook Request Error:\n{:#?}", req_err);
+            Err(errors::ServiceError::InternalServerError)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    // accepts one request and answers with `status`, giving back what it received
+    fn receiver(status: &'static str) -> (String, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0u8; 1024];
+            loop {
+                let n = stream.read(&mut buf).unwrap();
+                received.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&received).into_owned();
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let length = text.lines()
+                        .find(|l| l.to_lowercase().starts_with("content-length:"))
+                        .and_then(|l| l[15..].trim().parse::<usize>().ok())
+                        .unwrap_or_default();
+                    if end + 4 + length <= received.len() || n == 0 {
+                        break
+                    }
+                }
+            }
+            stream.write_all(format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).as_bytes()).unwrap();
+            String::from_utf8(received).unwrap()
+        });
+        (url, handle)
+    }
+    fn hook(url: String) -> models::Webhook {
+        models::Webhook {
+            id: 1,
+            owner: 1,
+            url: url,
+            secret: String::from("Jefe"),
+            events: Vec::new(),
+            is_active: true,
+            created_at: Utc::now(),
+        }
+    }
+    fn delivery() -> models::WebhookDelivery {
+        models::WebhookDelivery {
+            id: 7,
+            webhook: 1,
+            event: String::from("task.created"),
+            payload: String::from(r#"{"event":"task.created"}"#),
+            attempts: 0,
+            status: None,
+            error: None,
+            next_attempt_at: Some(Utc::now()),
+            delivered_at: None,
+            created_at: Utc::now(),
+        }
+    }
+    #[test]
+    fn t_sign() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+        );
+    }
+    #[test]
+    fn t_deliver() {
+        let (url, handle) = receiver("200 OK");
+        assert_eq!(deliver(&client().unwrap(), &hook(url), &delivery()), Ok(200));
+        let received = handle.join().unwrap().to_lowercase();
+        assert!(received.starts_with("post /hook http/1.1\r\n"));
+        assert!(received.contains("x-webhook-event: task.created\r\n"));
+        assert!(received.contains("x-webhook-delivery: 7\r\n"));
+        assert!(received.contains(&format!("x-webhook-signature: sha256={}\r\n", sign("Jefe", r#"{"event":"task.created"}"#))));
+        assert!(received.ends_with(r#"{"event":"task.created"}"#));
+
+        let (url, handle) = receiver("500 Internal Server Error");
+        assert!(matches!(deliver(&client().unwrap(), &hook(url), &delivery()), Err((Some(500), _))));
+        handle.join().unwrap();
+    }
+    #[test]
+    fn t_check_url() {
+        assert!(check_url("https://93.184.216.34/hook").is_ok());
+        assert!(check_url("https://[2606:2800:220:1:248:1893:25c8:1946]/hook").is_ok());
+        for url in &[
+            "ftp://93.184.216.34/hook",
+            "file:///etc/passwd",
+            "not a url",
+            "http://127.0.0.1:8080/hook",
+            "http://localhost/hook",
+            "http://10.0.0.1/hook",
+            "http://172.16.0.1/hook",
+            "http://192.168.1.1/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://0.0.0.0/hook",
+            "http://100.64.0.1/hook",
+            "http://[::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[fe80::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+        ] {
+            assert!(check_url(url).is_err(), "{}", url);
+        }
+    }
+    #[test]
+    fn t_retry_at() {
+        let now = Utc::now();
+        assert_eq!(retry_at(1, now), Some(now + Duration::seconds(30)));
+        assert_eq!(retry_at(3, now), Some(now + Duration::minutes(2)));
+        assert_eq!(retry_at(5, now), Some(now + Duration::minutes(8)));
+        assert_eq!(retry_at(6, now), None);
+    }
+}
@@ -601,8 +601,8 @@ clone mdl ids =
             [ [ item.id |> (\id -> "#" ++ U.int id)
               , item.isStarred |> BX.ifElse "*" ""
               , item.title
-              , item.startable |> MX.unwrap "" (\t -> U.clock True zone t ++ "-")
-              , item.deadline |> MX.unwrap "" (\t -> "-" ++ U.clock True zone t)
+              , item.startable |> MX.unwrap "" (\t -> U.clock True zone t ++ (item.isHardStartable |> BX.ifElse "!-" "-"))
+              , item.deadline |> MX.unwrap "" (\t -> (item.isHardDeadline |> BX.ifElse "-!" "-") ++ U.clock True zone t)
               , item.weight |> MX.unwrap "" (\w -> "$" ++ String.fromFloat w)
               , item.assign |> (++) "@"
               ]
@@ -788,7 +788,7 @@ viewItem mdl ( idx, item ) =
             , Request (Focus item) |> onClick
             , property "schedule" (item.schedule |> encSchedule mdl.user.zone)
             ]
-            [ item |> dotString mdl |> text ]
+            [ item.archivedAt |> MX.unwrap (item |> dotString mdl) (strArchived mdl item) |> text ]
         , td
             [ bem "deadline" [ ( "overdue", item |> isOverdue mdl ) ] ]
             [ item.deadline |> MX.unwrap "-" (U.strDT mdl.timescale mdl.user.zone) |> text ]
@@ -800,6 +800,14 @@ viewItem mdl ( idx, item ) =
         ]
 
 
+strArchived : Mdl -> Item -> Posix -> String
+strArchived mdl item at =
+    [ "Archived " ++ U.strDT mdl.timescale mdl.user.zone at
+    , item.archivedBy |> MX.unwrap "" (\name -> " by " ++ (name == mdl.user.name |> BX.ifElse "me" name))
+    ]
+        |> String.concat
+
+
 strPriority : Float -> String
 strPriority x =
     [ not (-1000 < x), not (x < 1000) ] |> U.overwrite (U.signedDecimal 1 x) [ "low", "high" ]
@@ -1208,11 +1216,15 @@ type alias Item =
     , isArchived : Bool
     , isStarred : Bool
     , startable : Maybe Posix
+    , isHardStartable : Bool
     , deadline : Maybe Posix
+    , isHardDeadline : Bool
     , priority : Maybe Float
     , weight : Maybe Float
     , link : Maybe String
     , schedule : Maybe Schedule
+    , archivedAt : Maybe Posix
+    , archivedBy : Maybe String
     }
 
 
@@ -1231,11 +1243,15 @@ decItem =
         |> required "is_archived" bool
         |> required "is_starred" bool
         |> required "startable" (nullable datetime)
+        |> required "is_hard_startable" bool
         |> required "deadline" (nullable datetime)
+        |> required "is_hard_deadline" bool
         |> required "priority" (nullable float)
         |> required "weight" (nullable float)
         |> required "link" (nullable string)
         |> required "schedule" (nullable decSchedule)
+        |> required "archived_at" (nullable datetime)
+        |> required "archived_by" (nullable string)
 
 
 decSchedule : Decoder Schedule
@@ -8,6 +8,9 @@ placeholder =
 
 indent  #id joint] * TITLE startable- -deadline $weight @assign [joint
         https://about/this
+        https://and/that labeled link
+        > a **markdown** note
+        > over lines
 
 jump
     step
@@ -17,8 +20,11 @@ jump
 *       star
 $24     expected to take 24 hours
 @user   assign to user
-12:-    can be started at 12:00 today
+12:-    rather not be started before 12:00 today
+12:!-   cannot be started before 12:00 today
 -/6/    should be done by 6/1 of this year
+-!/6/   must be done by 6/1 of this year, ahead of the others
+%proj   on the first line, put all into project proj
 
 root
     <!-- comment -->
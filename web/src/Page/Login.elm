@@ -26,12 +26,13 @@ type alias Req =
     { email : String
     , password : String
     , tz : String
+    , otp : String
     }
 
 
 init : ( Mdl, Cmd Msg )
 init =
-    ( { req = { email = "", password = "", tz = "" }
+    ( { req = { email = "", password = "", tz = "", otp = "" }
       , msg = ""
       , forgot_pw = False
       }
@@ -56,6 +57,7 @@ type FromU
     | ForgotPW
     | EditEmail String
     | EditPassWord String
+    | EditOtp String
 
 
 type FromS
@@ -117,6 +119,16 @@ update msg mdl =
                     in
                     ( { mdl | req = newReq }, Cmd.none )
 
+                EditOtp s ->
+                    let
+                        req =
+                            mdl.req
+
+                        newReq =
+                            { req | otp = s }
+                    in
+                    ( { mdl | req = newReq }, Cmd.none )
+
         FromS fromS ->
             case fromS of
                 LoggedIn (Err e) ->
@@ -129,10 +141,17 @@ update msg mdl =
 enc : Req -> Encode.Value
 enc req =
     Encode.object
-        [ ( "email", Encode.string req.email )
-        , ( "password", Encode.string req.password )
-        , ( "tz", Encode.string req.tz )
-        ]
+        ([ ( "email", Encode.string req.email )
+         , ( "password", Encode.string req.password )
+         , ( "tz", Encode.string req.tz )
+         ]
+            ++ (if String.isEmpty req.otp then
+                    []
+
+                else
+                    [ ( "otp", Encode.string req.otp ) ]
+               )
+        )
 
 
 
@@ -145,6 +164,7 @@ view mdl =
         [ h1 [ class "pre-app__title" ] [ text "Login" ]
         , div [] [ U.input "email" "Email" mdl.req.email EditEmail ]
         , div [] [ U.input "password" "Password" mdl.req.password EditPassWord ]
+        , div [] [ U.input "text" "2FA Code (if enabled)" mdl.req.otp EditOtp ]
         , div [] [ button [ onClick Login ] [ text "Login" ] ]
         , div [] [ button [ onClick NewAccount ] [ text "New Account" ] ]
         , div [] [ button [ onClick ForgotPW ] [ text "Forgot Password" ] ]
@@ -127,7 +127,10 @@ strHttpError e =
         Http.Detailed.BadStatus meta body ->
             case meta.statusCode of
                 400 ->
-                    "Oops, " ++ body
+                    "Oops, "
+                        ++ (Decode.decodeString (Decode.field "message" Decode.string) body
+                                |> Result.withDefault body
+                           )
 
                 401 ->
                     "Authentication failed."